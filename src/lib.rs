@@ -1,4 +1,8 @@
 pub mod parserv2;
+#[cfg(feature = "schema")]
+pub mod schema;
 pub mod serializer;
 pub mod types;
+pub mod validate;
+pub mod visit;
 
@@ -1,43 +1,105 @@
 use std::{borrow::Cow, collections::HashMap};
 
 use nom::{
-    self, PResult, Parser,
+    self, Parser,
     branch::alt,
     bytes::complete::*,
-    character::{
-        complete::{char, line_ending, multispace0, space0},
-        none_of,
-    },
-    combinator::opt,
+    character::complete::{char, line_ending, multispace0, multispace1, space0},
+    combinator::{eof, map, not, opt, peek},
     error::ParseError,
-    sequence::delimited,
+    sequence::{delimited, terminated},
 };
 
-use crate::types::{self, Class, Diagram, Direction, Namespace, Note, Relation};
+use crate::types::{
+    self, Class, Diagram, DiagramHeader, Direction, Member, Namespace, Note, Relation, Span,
+};
+use crate::visit::{VisitorMut, walk_diagram_mut};
 
 pub mod class;
+pub mod directive;
 pub mod frontmatter;
+pub mod markdown;
 pub mod namespace;
+pub mod reader;
 pub mod relation;
+pub mod style;
 
 #[derive(thiserror::Error, Debug, derive_more::From)]
 pub enum MermaidParseError {
-    #[error("{0:?}")]
+    #[error("{}", .0.description())]
     Nom(nom::error::ErrorKind),
     #[error("{0}")]
     SerdeYml(serde_yml::Error),
-    #[error("")]
+    /// Reading the input failed, see [`reader::parse_reader`]
+    #[error("{0}")]
+    Io(std::io::Error),
+    #[error("expected a classDiagram header")]
     ExpectedClassDiagram,
-    #[error("")]
-    ExpectedStmt,
+    /// A `direction` statement before the `classDiagram` header, where Mermaid doesn't allow it.
+    /// `offset` is the byte position of the `direction` keyword in the source.
+    #[error("direction has to come after the classDiagram header")]
+    #[from(skip)]
+    MisplacedDirection { offset: usize },
+    /// No statement could be parsed. `offset` is how many bytes of the source were consumed
+    /// before that, i.e. where the offending statement starts. `line` is the 1-based number of
+    /// the line it starts on and `text` that line, trimmed.
+    #[error("expected a statement at line {line}: {text}")]
+    #[from(skip)]
+    ExpectedStmt {
+        offset: usize,
+        line: usize,
+        text: String,
+    },
+    /// A name opened with a backtick that isn't closed on the same line. `offset` is the byte
+    /// position of the opening backtick in the source, `line` and `text` as for `ExpectedStmt`.
+    #[error("unterminated backtick-escaped name at line {line}: {text}")]
+    #[from(skip)]
+    UnterminatedBacktick {
+        offset: usize,
+        line: usize,
+        text: String,
+    },
+    /// A line in a class body that isn't a member, or members for a qualified class that doesn't
+    /// exist, only reported by [`parse_mermaid_strict`].
+    /// `offset` is the byte position of the start of that line in the source, `line` and `text`
+    /// as for `ExpectedStmt`.
+    #[error("invalid class member at line {line}: {text}")]
+    #[from(skip)]
+    InvalidMember {
+        offset: usize,
+        line: usize,
+        text: String,
+    },
+}
+
+impl MermaidParseError {
+    /// The parsers deep down don't know where they sit in the source, so they report the length
+    /// of the input left at the error as `offset` and leave the line empty. Turn that into a
+    /// position in `source`, which itself starts `start` bytes and `lines` lines into the input.
+    fn locate(mut self, source: &str, start: usize, lines: usize) -> Self {
+        if let MermaidParseError::ExpectedStmt { offset, line, text }
+        | MermaidParseError::UnterminatedBacktick { offset, line, text }
+        | MermaidParseError::InvalidMember { offset, line, text } = &mut self
+        {
+            let at = source.len() - *offset;
+            let line_start = source[..at].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = source[at..]
+                .find(['\r', '\n'])
+                .map_or(source.len(), |i| at + i);
+            *offset = start + at;
+            *line = lines + source[..line_start].matches('\n').count() + 1;
+            *text = source[line_start..line_end].trim().to_owned();
+        }
+        self
+    }
 }
 
 impl<I> ParseError<I> for MermaidParseError {
-    fn from_error_kind(input: I, kind: nom::error::ErrorKind) -> Self {
+    fn from_error_kind(_input: I, kind: nom::error::ErrorKind) -> Self {
         MermaidParseError::Nom(kind)
     }
 
-    fn append(input: I, kind: nom::error::ErrorKind, other: Self) -> Self {
+    fn append(_input: I, _kind: nom::error::ErrorKind, other: Self) -> Self {
         other
     }
 }
@@ -47,10 +109,29 @@ type IResult<I, O> = nom::IResult<I, O, MermaidParseError>;
 #[derive(Debug)]
 pub enum Stmt<'source> {
     Class(Class<'source>),
+    /// `class A, B, C`
+    Classes(Vec<Class<'source>>),
     Namespace(Namespace<'source>),
     Relation(Relation<'source>),
     Note(Note<'source>),
     Direction(Direction),
+    Style(types::StyleDirective<'source>),
+}
+
+impl Stmt<'_> {
+    pub fn into_owned(self) -> Stmt<'static> {
+        match self {
+            Stmt::Class(class) => Stmt::Class(class.into_owned()),
+            Stmt::Classes(classes) => {
+                Stmt::Classes(classes.into_iter().map(Class::into_owned).collect())
+            }
+            Stmt::Namespace(ns) => Stmt::Namespace(ns.into_owned()),
+            Stmt::Relation(rl) => Stmt::Relation(rl.into_owned()),
+            Stmt::Note(note) => Stmt::Note(note.into_owned()),
+            Stmt::Direction(dir) => Stmt::Direction(dir),
+            Stmt::Style(style) => Stmt::Style(style.into_owned()),
+        }
+    }
 }
 
 /// Parse mermaid line by line, keeping lines we failed to parse so they can be copied to the
@@ -61,114 +142,560 @@ pub enum Stmt<'source> {
 /// context we can only enter the class context.
 ///
 /// This parser was maded referencing version 11.12.0 of the Mermaid CLI. If there is a frontmatter
-pub fn parse_mermaid(source: &str) -> IResult<(), Diagram> {
+pub fn parse_mermaid(source: &str) -> Result<Diagram<'_>, MermaidParseError> {
+    parse_with_options(source, &ParseOptions::default())
+}
+
+/// [`parse_mermaid`] with [`ParseOptions::lenient`] set
+pub fn parse_mermaid_lenient(source: &str) -> Result<Diagram<'_>, MermaidParseError> {
+    let options = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    parse_with_options(source, &options)
+}
+
+/// [`parse_mermaid`] with [`ParseOptions::strict`] set
+pub fn parse_mermaid_strict(source: &str) -> Result<Diagram<'_>, MermaidParseError> {
+    let options = ParseOptions {
+        strict: true,
+        ..Default::default()
+    };
+    parse_with_options(source, &options)
+}
+
+/// Knobs controlling how a diagram is parsed. The defaults match [`parse_mermaid`].
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// A line in a class body that isn't a valid member is an
+    /// [`MermaidParseError::InvalidMember`] error instead of being silently dropped, and so is an
+    /// `Ns::Class : member` line for a class that doesn't exist
+    pub strict: bool,
+    /// Also accept spellings Mermaid itself rejects and that we can map unambiguously, such as
+    /// `direction down` for `direction TB`
+    pub lenient: bool,
+    /// Decode entities such as `#quot;` and `#35;` in note text and relation labels (see
+    /// [`relation::decode_label_entities`]) instead of keeping them as written
+    pub decode_entities: bool,
+    /// Let a method's parameter list continue on the next line after a `\` at the end of a line,
+    /// which some generators emit for long signatures
+    pub allow_line_continuation: bool,
+    /// Record the [`Span`] of each class, member, relation and note. Off by default, since
+    /// spans make diagrams parsed from differently laid out sources compare unequal. A member
+    /// added with `ClassName : member` spans that whole line.
+    pub spans: bool,
+}
+
+/// Parse `source` using the given options
+pub fn parse_with_options<'source>(
+    source: &'source str,
+    options: &ParseOptions,
+) -> Result<Diagram<'source>, MermaidParseError> {
+    let mut diagram = parse_whole_document(source, options)?;
+
+    if options.decode_entities {
+        for note in &mut diagram.notes {
+            note.text = decode_entities(std::mem::take(&mut note.text));
+        }
+        for relation in &mut diagram.relations {
+            relation.label = relation.label.take().map(decode_entities);
+        }
+    }
+
+    Ok(diagram)
+}
+
+/// [`relation::decode_label_entities`], keeping the text borrowed from the source if we can
+fn decode_entities(text: Cow<'_, str>) -> Cow<'_, str> {
+    match text {
+        Cow::Borrowed(text) => relation::decode_label_entities(text),
+        Cow::Owned(text) => Cow::Owned(relation::decode_label_entities(&text).into_owned()),
+    }
+}
+
+/// Parse the document and fail unless all of it was understood
+fn parse_whole_document<'source>(
+    source: &'source str,
+    options: &ParseOptions,
+) -> Result<Diagram<'source>, MermaidParseError> {
+    let (diagram, rest) = parse_document(source, options)?;
+    if !rest.is_empty() {
+        return Err(stuck(source, rest, 0, 0, options));
+    }
+    Ok(diagram)
+}
+
+/// Work out why parsing stopped at `rest`, the unparsed tail of `source`. `source` starts `start`
+/// bytes and `lines` lines into the input, see [`MermaidParseError::locate`].
+fn stuck(
+    source: &str,
+    rest: &str,
+    start: usize,
+    lines: usize,
+    options: &ParseOptions,
+) -> MermaidParseError {
+    // Members for a qualified class that doesn't exist are the only thing we stop at while
+    // understanding them, see `body_items`
+    if options.strict
+        && let Ok((_, Item::Members(..))) = body_item(rest, options)
+    {
+        let error = MermaidParseError::InvalidMember {
+            offset: rest.len(),
+            line: 0,
+            text: String::new(),
+        };
+        return error.locate(source, start, lines);
+    }
+
+    // Run the statement that stopped us again to find out why
+    let error = match stmt(rest, options) {
+        Err(nom::Err::Failure(
+            error @ (MermaidParseError::UnterminatedBacktick { .. }
+            | MermaidParseError::InvalidMember { .. }),
+        )) => error,
+        _ => MermaidParseError::ExpectedStmt {
+            offset: rest.len(),
+            line: 0,
+            text: String::new(),
+        },
+    };
+    error.locate(source, start, lines)
+}
+
+/// Parse the statements of a diagram body on their own, without the `classDiagram` header or any
+/// frontmatter. Handy for checking a snippet of a larger diagram.
+pub fn parse_body(source: &str) -> Result<Diagram<'_>, MermaidParseError> {
+    let options = ParseOptions::default();
+    let mut builder = DiagramBuilder::new(Diagram::default());
+    let rest = builder.parse_body(source, &options);
+    if !rest.is_empty() {
+        return Err(stuck(source, rest, 0, 0, &options));
+    }
+    Ok(builder.diagram)
+}
+
+/// Parse an input holding several diagrams one after another, each starting with its own
+/// `classDiagram` header or frontmatter, as some files concatenate them.
+pub fn parse_all(source: &str) -> Result<Vec<Diagram<'_>>, MermaidParseError> {
+    let options = ParseOptions::default();
+    let (diagram, mut rest) = parse_document(source, &options)?;
+    let mut diagrams = vec![diagram];
+    loop {
+        let next = rest.trim_start();
+        if next.is_empty() {
+            return Ok(diagrams);
+        }
+        if !next.starts_with("---") && class_diagram(next).is_err() {
+            return Err(stuck(source, rest, 0, 0, &options));
+        }
+        let (diagram, tail) = parse_document(next, &options)?;
+        diagrams.push(diagram);
+        rest = tail;
+    }
+}
+
+/// Parse as much of `source` as possible, returning the diagram along with the unparsed tail.
+/// Parsing stops at the first statement that isn't understood, so trailing prose after a diagram
+/// ends up in the remainder. If there is no diagram header at all the whole input is returned.
+pub fn parse_partial(source: &str) -> (Diagram<'_>, &str) {
+    parse_document(source, &ParseOptions::default())
+        .unwrap_or_else(|_| (Diagram::default(), source))
+}
+
+fn parse_document<'source>(
+    source: &'source str,
+    options: &ParseOptions,
+) -> Result<(Diagram<'source>, &'source str), MermaidParseError> {
+    let (body, head) = parse_head(source)?;
+    let mut builder = DiagramBuilder::new(head);
+    let rest = builder.parse_body(body, options);
+    if options.spans {
+        walk_diagram_mut(
+            &mut ShiftSpans(source.len() - body.len()),
+            &mut builder.diagram,
+        );
+    }
+    Ok((builder.diagram, rest))
+}
+
+/// Parse everything up to and including the diagram header, returning an empty diagram that
+/// carries the frontmatter and directives.
+fn parse_head(source: &str) -> Result<(&str, Diagram<'_>), MermaidParseError> {
     // First line MUST be --- unindented if we have a frontmatter
-    let (mut document, yaml) = frontmatter::frontmatter(source)?;
+    let (mut document, frontmatter) = frontmatter::frontmatter_raw(source).map_err(finish)?;
+    let (raw_yaml, yaml) = frontmatter.unzip();
 
-    // Then we can have comments until a diagram definition
-    while let Ok((rem, _)) = ws(comment).parse(document) {
-        document = rem;
+    // Then we can have comments and directives until a diagram definition
+    let mut directives = Vec::new();
+    loop {
+        if let Ok((rem, value)) = ws(directive::directive).parse(document) {
+            directives.push(value);
+            document = rem;
+        } else if let Ok((rem, _)) = ws(comment).parse(document) {
+            document = rem;
+        } else {
+            break;
+        }
     }
 
-    let Ok((mut body, _)) = class_diagram(document) else {
-        return Err(nom::Err::Failure(MermaidParseError::ExpectedClassDiagram));
+    let Ok((body, header)) = class_diagram(document) else {
+        // Point at a `direction` that should have gone after the header
+        if let Ok((rem, _)) = namespace::stmt_direction_lenient(document)
+            && class_diagram(rem).is_ok()
+        {
+            let offset = source.len() - document.trim_start().len();
+            return Err(MermaidParseError::MisplacedDirection { offset });
+        }
+        return Err(MermaidParseError::ExpectedClassDiagram);
     };
 
-    // Then we can parse the body of the diagram
-    let mut namespaces: HashMap<Cow<str>, Namespace> = HashMap::new();
-    // Initialize the default namespace
-    namespaces.insert(
-        Cow::Borrowed(types::DEFAULT_NAMESPACE),
-        Namespace {
-            name: Cow::Borrowed(types::DEFAULT_NAMESPACE),
-            classes: HashMap::new(),
-            children: HashMap::new(),
-        },
-    );
-    let mut relations = Vec::new();
-    let mut notes = Vec::new();
-    let mut direction = None;
+    let diagram = Diagram {
+        header,
+        yaml,
+        raw_yaml: raw_yaml.map(Cow::Borrowed),
+        directives,
+        ..Default::default()
+    };
+    Ok((body, diagram))
+}
 
-    while !body.is_empty() {
-        // Skip whitespace
-        match multispace0::<_, nom::error::Error<_>>(body) {
-            Ok((rem, _)) => body = rem,
-            Err(_) => break,
+/// One thing found in the body of a diagram
+enum Item<'source> {
+    Directive(serde_yml::Value),
+    Comment,
+    /// `ClassName : <<annotation>>` or `<<annotation>> ClassName`
+    Annotation(Cow<'source, str>, Cow<'source, str>),
+    /// `ClassName : member, member`
+    Members(Cow<'source, str>, Vec<types::Member<'source>>),
+    Stmt(Stmt<'source>),
+}
+
+impl Item<'_> {
+    fn into_owned(self) -> Item<'static> {
+        let owned = |sym: Cow<'_, str>| Cow::Owned(sym.into_owned());
+        match self {
+            Item::Directive(value) => Item::Directive(value),
+            Item::Comment => Item::Comment,
+            Item::Annotation(class, annotation) => {
+                Item::Annotation(owned(class), owned(annotation))
+            }
+            Item::Members(class, members) => Item::Members(
+                owned(class),
+                members.into_iter().map(types::Member::into_owned).collect(),
+            ),
+            Item::Stmt(stmt) => Item::Stmt(stmt.into_owned()),
+        }
+    }
+}
+
+/// The next item in the body. `body` must not start with whitespace.
+fn body_item<'a>(body: &'a str, options: &ParseOptions) -> IResult<&'a str, Item<'a>> {
+    // Keep directives, skip other comments
+    if let Ok((rem, value)) = directive::directive(body) {
+        return Ok((rem, Item::Directive(value)));
+    }
+    if let Ok((rem, _)) = comment(body) {
+        return Ok((rem, Item::Comment));
+    }
+
+    // Try to parse "ClassName : member" statement first
+    if let Ok((s_new, class_name)) = class::class_name(body)
+        && let Ok((s_new2, _)) = space0::<_, nom::error::Error<_>>(s_new)
+        && let Ok((s_new3, _)) = char::<_, nom::error::Error<_>>(':')(s_new2)
+    {
+        let (s_new4, _) = space0::<_, nom::error::Error<_>>(s_new3).unwrap_or((s_new3, ""));
+
+        // "ClassName : <<annotation>>" annotates the class instead of adding a member
+        if let Ok((s_new5, annotation)) = class::class_annotation(s_new4) {
+            let item = Item::Annotation(Cow::Borrowed(class_name), Cow::Borrowed(annotation));
+            return Ok((s_new5, item));
         }
 
-        // Skip comments
-        match comment(body) {
-            Ok((rem, _)) => body = rem,
-            Err(_) => break,
+        if let Ok((s_new5, mut members)) = class::class_member_list_with(s_new4, options) {
+            if options.spans {
+                for member in &mut members {
+                    *member_span_mut(member) = Some(span_in(body, body, s_new5));
+                }
+            }
+            return Ok((s_new5, Item::Members(Cow::Borrowed(class_name), members)));
         }
+    }
+
+    // "<<annotation>> ClassName" on its own line works like "ClassName : <<annotation>>"
+    if let Ok((rem, (class_name, annotation))) = class::annotation_stmt(body) {
+        let item = Item::Annotation(Cow::Borrowed(class_name), Cow::Borrowed(annotation));
+        return Ok((rem, item));
+    }
 
+    let (rem, mut stmt) = stmt(body, options)?;
+    if options.spans
+        && let Stmt::Note(note) = &mut stmt
+    {
+        note.span = Some(span_in(body, body, rem));
+    }
+    Ok((rem, Item::Stmt(stmt)))
+}
+
+/// Hand each item in `body` to `f` until one isn't understood, returning the rest of `body`.
+/// Spans are relative to the start of `body`. `f` returns whether it could use the item; in
+/// strict mode we stop at one it couldn't.
+fn body_items<'a>(
+    mut body: &'a str,
+    options: &ParseOptions,
+    mut f: impl FnMut(Item<'a>) -> bool,
+) -> &'a str {
+    let input = body;
+    loop {
+        // Skip whitespace
+        body = body.trim_start_matches([' ', '\t', '\r', '\n']);
         if body.is_empty() {
-            break;
+            break body;
         }
+        match body_item(body, options) {
+            Ok((rem, mut item)) => {
+                if options.spans {
+                    ShiftSpans(input.len() - body.len()).item(&mut item);
+                }
+                if !f(item) && options.strict {
+                    break body;
+                }
+                // Some generators end statements with a `;`
+                body = rem.trim_start_matches([' ', '\t']);
+                body = body.strip_prefix(';').unwrap_or(body);
+            }
+            // Leave the rest for the caller to deal with
+            Err(_why) => break body,
+        }
+    }
+}
+
+/// Collects the items of a diagram body into a [`Diagram`]
+struct DiagramBuilder<'source> {
+    diagram: Diagram<'source>,
+    next_order: usize,
+}
+
+impl<'source> DiagramBuilder<'source> {
+    fn new(mut diagram: Diagram<'source>) -> Self {
+        // Initialize the default namespace
+        diagram.namespaces.insert(
+            Cow::Borrowed(types::DEFAULT_NAMESPACE),
+            Namespace {
+                name: Cow::Borrowed(types::DEFAULT_NAMESPACE),
+                classes: HashMap::new(),
+                children: HashMap::new(),
+                direction: None,
+            },
+        );
+        DiagramBuilder {
+            diagram,
+            next_order: 0,
+        }
+    }
+
+    /// Parse items until one isn't understood, returning the rest of `body`
+    fn parse_body(&mut self, body: &'source str, options: &ParseOptions) -> &'source str {
+        body_items(body, options, |item| self.apply(item))
+    }
+
+    /// The class `name` refers to. Like Mermaid, a plain name that hasn't been declared yet
+    /// declares the class in the default namespace, while a qualified name has to match an
+    /// existing class.
+    fn class_or_declare(&mut self, name: Cow<'source, str>) -> Option<&mut Class<'source>> {
+        if !name.contains("::") && self.diagram.find_class(&name).is_none() {
+            let class = Class {
+                name: name.clone(),
+                generic: None,
+                label: None,
+                css_class: None,
+                annotations: Vec::new(),
+                members: Vec::new(),
+                order: 0,
+                span: None,
+            };
+            self.apply(Item::Stmt(Stmt::Class(class)));
+        }
+        self.diagram.find_class_mut(&name)
+    }
 
-        // Try to parse "ClassName : member" statement first
-        if let Ok((s_new, class_name)) = class::class_name(body) {
-            let s_new2_result = space0::<_, nom::error::Error<_>>(s_new);
-            if let Ok((s_new2, _)) = s_new2_result {
-                if let Ok((s_new3, _)) = char::<_, nom::error::Error<_>>(':')(s_new2) {
-                    let (s_new4, _) =
-                        space0::<_, nom::error::Error<_>>(s_new3).unwrap_or((s_new3, ""));
-                    if let Ok((s_new5, member)) = class::class_member_stmt(s_new4) {
-                        // Add member to the class in default namespace
-                        if let Some(class) = namespaces
-                            .get_mut(types::DEFAULT_NAMESPACE)
-                            .and_then(|ns| ns.classes.get_mut(&Cow::Borrowed(class_name)))
-                        {
-                            class.members.push(member);
-                        }
-                        body = s_new5;
-                        continue;
-                    }
+    /// Add `item` to the diagram. Returns false when it was dropped, which only happens to members
+    /// of a qualified class that doesn't exist.
+    fn apply(&mut self, item: Item<'source>) -> bool {
+        match item {
+            Item::Directive(value) => self.diagram.directives.push(value),
+            Item::Comment => {}
+            // `Ns::Class` reaches into a namespace
+            Item::Annotation(class, annotation) => {
+                if let Some(class) = self.class_or_declare(class) {
+                    class.annotate(annotation.into());
+                }
+            }
+            Item::Members(class, members) => {
+                let Some(class) = self.class_or_declare(class) else {
+                    return false;
+                };
+                class.members.extend(members);
+            }
+            Item::Stmt(Stmt::Class(class)) => {
+                let default = self.diagram.namespaces.get_mut(types::DEFAULT_NAMESPACE);
+                let default = default.expect("This should exist");
+                declare_class(&mut default.classes, class, &mut self.next_order);
+            }
+            Item::Stmt(Stmt::Classes(classes)) => {
+                for class in classes {
+                    self.apply(Item::Stmt(Stmt::Class(class)));
+                }
+            }
+            Item::Stmt(Stmt::Namespace(mut ns)) => {
+                // Classes come back numbered within the block, shift them after what we've seen
+                let mut classes = ns.all_classes_mut();
+                classes.sort_by_key(|class| class.order);
+                for class in classes {
+                    class.order = self.next_order;
+                    self.next_order += 1;
                 }
+                self.diagram.namespaces.insert(ns.name.clone(), ns);
             }
+            Item::Stmt(Stmt::Relation(rl)) => self.diagram.relations.push(rl),
+            Item::Stmt(Stmt::Note(note)) => self.diagram.notes.push(note),
+            Item::Stmt(Stmt::Direction(dir)) => self.diagram.direction = Some(dir),
+            Item::Stmt(Stmt::Style(style)) => self.diagram.styles.push(style),
         }
+        true
+    }
+}
 
-        // NOTE: For this combinator to implement parse we actually need the same output type on
-        // all out stmts. Which is why the enum exists.
-        let result = alt((
-            class::class_stmt,
-            namespace::namespace_stmt,
-            relation::relation_stmt,
-            note_stmt,
-            direction_stmt,
-        ))
-        .parse_complete(body);
-
-        match result.map(|(rem, stmt)| {
-            body = rem;
-            stmt
-        }) {
-            Err(_why) => {
-                return Err(nom::Err::Failure(MermaidParseError::ExpectedStmt));
+/// Insert a declared class, numbering it with the next declaration order. A class that was
+/// declared before keeps its place.
+fn declare_class<'source>(
+    classes: &mut HashMap<Cow<'source, str>, Class<'source>>,
+    mut class: Class<'source>,
+    next_order: &mut usize,
+) {
+    use std::collections::hash_map::Entry;
+
+    match classes.entry(class.name.clone()) {
+        // Declaring a class again adds to it like in Mermaid, so members given with
+        // `Name : member` before the `class` statement are kept
+        Entry::Occupied(mut entry) => {
+            let existing = entry.get_mut();
+            existing.generic = class.generic.or(existing.generic.take());
+            existing.label = class.label.or(existing.label.take());
+            existing.css_class = class.css_class.or(existing.css_class.take());
+            existing.span = existing.span.or(class.span);
+            for annotation in class.annotations {
+                existing.annotate(annotation);
             }
-            Ok(Stmt::Class(class)) => {
-                namespaces
-                    .get_mut(types::DEFAULT_NAMESPACE)
-                    .expect("This should exist")
-                    .classes
-                    .insert(class.name.clone(), class);
+            existing.members.extend(class.members);
+        }
+        Entry::Vacant(entry) => {
+            class.order = *next_order;
+            *next_order += 1;
+            entry.insert(class);
+        }
+    }
+}
+
+/// Where the statement parsed from `before`, leaving `after`, sits in `input`, without the
+/// whitespace around it
+fn span_in(input: &str, before: &str, after: &str) -> Span {
+    let text = &before[..before.len() - after.len()];
+    let trimmed = text.trim_start();
+    let start = input.len() - before.len() + (text.len() - trimmed.len());
+    Span {
+        start,
+        end: start + trimmed.trim_end().len(),
+    }
+}
+
+fn member_span_mut<'a>(member: &'a mut Member<'_>) -> &'a mut Option<Span> {
+    match member {
+        Member::Attribute(attr) => &mut attr.span,
+        Member::Method(method) => &mut method.span,
+    }
+}
+
+/// The parsers record spans relative to their own input, as they don't know where it sits in the
+/// source. This moves them the given number of bytes further in once the caller does.
+struct ShiftSpans(usize);
+
+impl ShiftSpans {
+    fn shift(&self, span: &mut Option<Span>) {
+        *span = span.map(|span| span.shift(self.0));
+    }
+
+    fn class(&mut self, class: &mut Class<'_>) {
+        self.visit_class_mut(class);
+        for member in &mut class.members {
+            self.visit_member_mut(member);
+        }
+    }
+
+    fn item(&mut self, item: &mut Item<'_>) {
+        match item {
+            Item::Members(_, members) => {
+                for member in members {
+                    self.visit_member_mut(member);
+                }
             }
-            Ok(Stmt::Namespace(ns)) => {
-                namespaces.insert(ns.name.clone(), ns);
+            Item::Stmt(Stmt::Class(class)) => self.class(class),
+            Item::Stmt(Stmt::Classes(classes)) => classes.iter_mut().for_each(|c| self.class(c)),
+            Item::Stmt(Stmt::Namespace(ns)) => {
+                ns.all_classes_mut().into_iter().for_each(|c| self.class(c))
             }
-            Ok(Stmt::Relation(rl)) => relations.push(rl),
-            Ok(Stmt::Note(note)) => notes.push(note),
-            Ok(Stmt::Direction(dir)) => direction = Some(dir),
+            Item::Stmt(Stmt::Relation(relation)) => self.visit_relation_mut(relation),
+            Item::Stmt(Stmt::Note(note)) => self.visit_note_mut(note),
+            _ => {}
         }
     }
+}
 
-    Ok(((), Diagram {
-        namespaces,
-        relations,
-        notes,
-        direction,
-        yaml,
-    }))
+impl<'source> VisitorMut<'source> for ShiftSpans {
+    fn visit_class_mut(&mut self, class: &mut Class<'source>) {
+        self.shift(&mut class.span);
+    }
+
+    fn visit_member_mut(&mut self, member: &mut Member<'source>) {
+        self.shift(member_span_mut(member));
+    }
+
+    fn visit_relation_mut(&mut self, relation: &mut Relation<'source>) {
+        self.shift(&mut relation.span);
+    }
+
+    fn visit_note_mut(&mut self, note: &mut Note<'source>) {
+        self.shift(&mut note.span);
+    }
+}
+
+/// Strip the nom wrapper off an error. We always parse complete input so `Incomplete` can't
+/// really happen, but map it to something sensible anyway.
+fn finish(err: nom::Err<MermaidParseError>) -> MermaidParseError {
+    match err {
+        nom::Err::Error(e) | nom::Err::Failure(e) => e,
+        nom::Err::Incomplete(_) => MermaidParseError::Nom(nom::error::ErrorKind::Complete),
+    }
+}
+
+/// Any top level statement
+fn stmt<'a>(s: &'a str, options: &ParseOptions) -> IResult<&'a str, Stmt<'a>> {
+    // NOTE: For this combinator to implement parse we actually need the same output type on
+    // all out stmts. Which is why the enum exists.
+    alt((
+        |s| class::class_stmt_with(s, options),
+        |s| namespace::namespace_stmt_with(s, options),
+        style::style_stmt,
+        |s| relation::relation_stmt_with(s, options),
+        note_stmt,
+        |s| {
+            if options.lenient {
+                direction_stmt_lenient(s)
+            } else {
+                direction_stmt(s)
+            }
+        },
+    ))
+    .parse_complete(s)
 }
 
 fn delete_match<I, O>(val: (I, O)) -> (I, ()) {
@@ -182,10 +709,13 @@ where
     delimited(multispace0, inner, opt(multispace0))
 }
 
-pub fn class_diagram(s: &str) -> IResult<&str, ()> {
-    ws(alt((tag("classDiagram-v2"), tag("classDiagram"))))
-        .parse_complete(s)
-        .map(delete_match)
+pub fn class_diagram(s: &str) -> IResult<&str, DiagramHeader> {
+    // The keyword has to end there, so `classDiagramX` isn't a header
+    let header = alt((
+        map(tag("classDiagram-v2"), |_| DiagramHeader::ClassDiagramV2),
+        map(tag("classDiagram"), |_| DiagramHeader::ClassDiagram),
+    ));
+    ws(terminated(header, peek(alt((multispace1, eof))))).parse_complete(s)
 }
 
 // Original parsing for these are done with the following two regex:
@@ -197,6 +727,18 @@ pub fn comment(s: &str) -> IResult<&str, ()> {
         .map(delete_match)
 }
 
+/// Skip the rest of the current line along with its `\n` or `\r\n` ending. Fails at the end of
+/// the input so loops skipping lines they don't understand can't spin forever.
+fn skip_line(s: &str) -> IResult<&str, ()> {
+    (
+        not(eof),
+        take_while(|c| c != '\n' && c != '\r'),
+        opt(line_ending),
+    )
+        .parse(s)
+        .map(delete_match)
+}
+
 pub fn note_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'source>> {
     let (s, note) = namespace::stmt_note(s)?;
     Ok((s, Stmt::Note(note)))
@@ -207,9 +749,15 @@ pub fn direction_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'s
     Ok((s, Stmt::Direction(direction)))
 }
 
+pub fn direction_stmt_lenient<'source>(s: &'source str) -> IResult<&'source str, Stmt<'source>> {
+    let (s, direction) = namespace::stmt_direction_lenient(s)?;
+    Ok((s, Stmt::Direction(direction)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::AnnotationKind;
 
     #[test]
     fn test_comment() {
@@ -239,6 +787,456 @@ mod tests {
         assert_eq!(remainder, "classDiagram", "We should strip the endline.");
     }
 
+    #[test]
+    fn test_class_diagram() {
+        let (rem, header) = class_diagram("classDiagram\nclass A").expect("Failed to parse header");
+        assert_eq!(rem, "class A");
+        assert_eq!(header, DiagramHeader::ClassDiagram);
+
+        let (rem, header) =
+            class_diagram("  classDiagram-v2\nclass A").expect("Failed to parse v2 header");
+        assert_eq!(rem, "class A");
+        assert_eq!(header, DiagramHeader::ClassDiagramV2);
+
+        let (rem, _) = class_diagram("classDiagram").expect("Failed to parse bare header");
+        assert!(rem.is_empty());
+        assert!(class_diagram("classDiagramclassroom\n").is_err());
+        assert!(class_diagram("classDiagram-v2classroom\n").is_err());
+        assert!(class_diagram("classroom\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_mermaid_class_keyword_prefix() {
+        let diagram =
+            parse_mermaid("classDiagram\nclass classroom\nclassroom --> classDiagramV3\n").unwrap();
+        assert!(diagram.find_class("classroom").is_some());
+        assert_eq!(diagram.relations[0].tail, "classroom");
+        assert_eq!(diagram.relations[0].head, "classDiagramV3");
+
+        let diagram = parse_mermaid("classDiagram\nclassroom : +seats int\n").unwrap();
+        assert_eq!(diagram.find_class("classroom").unwrap().members.len(), 1);
+
+        assert!(matches!(
+            parse_mermaid("classDiagramclassroom\n"),
+            Err(MermaidParseError::ExpectedClassDiagram)
+        ));
+    }
+
+    #[test]
+    fn test_parse_mermaid_misplaced_direction() {
+        let source = "%% layout\ndirection LR\nclassDiagram\nclass A\n";
+        let Err(err @ MermaidParseError::MisplacedDirection { offset }) = parse_mermaid(source)
+        else {
+            panic!("The misplaced direction should be reported");
+        };
+        assert_eq!(&source[offset..], "direction LR\nclassDiagram\nclass A\n");
+        assert_eq!(
+            err.to_string(),
+            "direction has to come after the classDiagram header"
+        );
+
+        // Without a header to follow it's just not a class diagram
+        assert!(matches!(
+            parse_mermaid("direction LR\nflowchart\n"),
+            Err(MermaidParseError::ExpectedClassDiagram)
+        ));
+    }
+
+    #[test]
+    fn test_parse_mermaid_chained_relations() {
+        let diagram = parse_mermaid("classDiagram\nA --> B --> C\nC <|-- D : label\n")
+            .expect("Failed to parse chained relations");
+        assert_eq!(diagram.relations.len(), 3);
+        assert_eq!(diagram.relations[0].tail, "A");
+        assert_eq!(diagram.relations[0].head, "B");
+        assert_eq!(diagram.relations[1].tail, "B");
+        assert_eq!(diagram.relations[1].head, "C");
+        assert_eq!(diagram.relations[2].tail, "D");
+        assert_eq!(diagram.relations[2].head, "C");
+        assert_eq!(diagram.relations[2].label, Some("label".into()));
+    }
+
+    #[test]
+    fn test_parse_mermaid_expected_stmt_offset() {
+        let result = parse_mermaid("classDiagram\nclass A\n  ??? not mermaid\nclass B\n");
+        assert!(matches!(
+            result,
+            Err(MermaidParseError::ExpectedStmt { offset: 23, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_mermaid_error_message() {
+        let error =
+            parse_mermaid("classDiagram\nclass A\n  ??? not mermaid\nclass B\n").unwrap_err();
+        assert!(matches!(
+            &error,
+            MermaidParseError::ExpectedStmt { line: 3, text, .. } if text == "??? not mermaid"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "expected a statement at line 3: ??? not mermaid"
+        );
+
+        let error = parse_mermaid("classDiagram\r\nclass `Unclosed\r\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unterminated backtick-escaped name at line 2: class `Unclosed"
+        );
+
+        let error = parse_mermaid_strict("classDiagram\nclass A {\n  +id: int\n  +++garbage\n}\n")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid class member at line 4: +++garbage"
+        );
+
+        let error = parse_mermaid("flowchart LR\n").unwrap_err();
+        assert_eq!(error.to_string(), "expected a classDiagram header");
+    }
+
+    #[test]
+    fn test_parse_mermaid_unterminated_backtick() {
+        let result = parse_mermaid("classDiagram\nclass `Unclosed\n");
+        assert!(matches!(
+            result,
+            Err(MermaidParseError::UnterminatedBacktick { offset: 19, .. })
+        ));
+
+        let result = parse_mermaid("classDiagram\nclass A\n`Broken --> A\n");
+        assert!(matches!(
+            result,
+            Err(MermaidParseError::UnterminatedBacktick { offset: 21, .. })
+        ));
+
+        // Closing on the same line is still fine
+        assert!(parse_mermaid("classDiagram\nclass `Closed Name`\n").is_ok());
+    }
+
+    #[test]
+    fn test_parse_mermaid_directives() {
+        let diagram = parse_mermaid(
+            "%%{init: {'theme':'forest'}}%%\nclassDiagram\n%%{wrap}%%\nclass A\n%% not a directive\n",
+        )
+        .expect("Failed to parse directives");
+        assert_eq!(diagram.directives.len(), 2);
+        assert_eq!(diagram.directives[0]["init"]["theme"], "forest");
+        assert!(
+            diagram.namespaces[types::DEFAULT_NAMESPACE]
+                .classes
+                .contains_key("A")
+        );
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let source = "classDiagram\nclass A\nA --> B\n\nThat's all for the design.\n";
+        let (diagram, rest) = parse_partial(source);
+        assert_eq!(diagram.relations.len(), 1);
+        assert_eq!(rest, "That's all for the design.\n");
+        assert!(parse_mermaid(source).is_err());
+
+        let (diagram, rest) = parse_partial("classDiagram\nclass A\n");
+        assert!(
+            diagram.namespaces[types::DEFAULT_NAMESPACE]
+                .classes
+                .contains_key("A")
+        );
+        assert!(rest.is_empty());
+
+        let (diagram, rest) = parse_partial("not a diagram");
+        assert_eq!(diagram, Diagram::default());
+        assert_eq!(rest, "not a diagram");
+    }
+
+    #[test]
+    fn test_parse_mermaid_empty_body() {
+        let sources = [
+            "classDiagram",
+            "classDiagram\n\n\n",
+            "classDiagram   \r\n  \t\r\n",
+            "classDiagram\n%% nothing yet\n  %% still nothing\n\n",
+        ];
+        for source in sources {
+            let diagram = parse_mermaid(source).expect(source);
+            assert_eq!(
+                diagram.namespaces.keys().collect::<Vec<_>>(),
+                [types::DEFAULT_NAMESPACE],
+                "{source:?}"
+            );
+            assert_eq!(diagram.class_count(), 0, "{source:?}");
+            assert!(diagram.relations.is_empty() && diagram.notes.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_parse_body() {
+        let diagram = parse_body("class A\nA --> B").unwrap();
+        assert!(
+            diagram.namespaces[types::DEFAULT_NAMESPACE]
+                .classes
+                .contains_key("A")
+        );
+        assert_eq!(diagram.relations.len(), 1);
+        assert_eq!(diagram.relations[0].head, "B");
+
+        assert!(matches!(
+            parse_body("class A\nnonsense here"),
+            Err(MermaidParseError::ExpectedStmt { offset: 8, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let source = "classDiagram\nclass A\nA --> B\n\nclassDiagram-v2\nclass C\n---\ntitle: Third\n---\nclassDiagram\nclass D\n";
+        let diagrams = parse_all(source).unwrap();
+        assert_eq!(diagrams.len(), 3);
+        assert_eq!(diagrams[0].class_count(), 1);
+        assert_eq!(diagrams[0].relations.len(), 1);
+        assert_eq!(diagrams[1].header, DiagramHeader::ClassDiagramV2);
+        assert!(diagrams[1].find_class("C").is_some());
+        assert_eq!(diagrams[2].title(), Some("Third"));
+        assert!(diagrams[2].find_class("D").is_some());
+
+        assert_eq!(parse_all("classDiagram\nclass A\n").unwrap().len(), 1);
+        assert!(matches!(
+            parse_all("classDiagram\nclass A\nclassDiagram\n!!!\n"),
+            Err(MermaidParseError::ExpectedStmt { line: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_mermaid_interleaved_comments() {
+        let source = "classDiagram\n%% the classes\nclass A\n  %% indented\nclass B\n%%\nA --> B : uses %% not a comment\n%% members\nB : +id int\n%% trailing";
+        let diagram = parse_mermaid(source).unwrap();
+        assert_eq!(diagram.class_count(), 2);
+        assert_eq!(diagram.relations.len(), 1);
+        // A label runs to the end of the line
+        assert_eq!(
+            diagram.relations[0].label,
+            Some("uses %% not a comment".into())
+        );
+        assert_eq!(
+            diagram.namespaces[types::DEFAULT_NAMESPACE].classes["B"]
+                .members
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_parse_mermaid_semicolons() {
+        let source = "classDiagram\ndirection LR;\nclass A;\nclass B {\n  +id: int\n};\nclass C, D ;\nA --> B;\nA \"1\" --> \"*\" C : owns;\nB : +run() void;\nnote for A \"hi\";\n";
+        let diagram = parse_mermaid(source).unwrap();
+        assert_eq!(diagram.class_count(), 4);
+        assert_eq!(diagram.relations.len(), 2);
+        assert_eq!(diagram.relations[0].label, None);
+        assert_eq!(diagram.relations[1].label, Some("owns".into()));
+        assert_eq!(diagram.notes.len(), 1);
+        assert_eq!(
+            diagram.namespaces[types::DEFAULT_NAMESPACE].classes["B"]
+                .members
+                .len(),
+            2
+        );
+        assert_eq!(diagram, parse_mermaid(&source.replace(';', "")).unwrap());
+    }
+
+    #[test]
+    fn test_parse_mermaid_member_declares_class() {
+        let source = "classDiagram\nFoo : +x int\nFoo : <<interface>>\nBar : <<service>>\nclass Baz\nGhost::Thing : +y int\n";
+        let diagram = parse_mermaid(source).unwrap();
+        let classes = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes;
+        assert_eq!(classes["Foo"].members.len(), 1);
+        assert_eq!(classes["Foo"].annotations, [AnnotationKind::Interface]);
+        assert_eq!(classes["Foo"].order, 0);
+        assert_eq!(classes["Bar"].annotations, [AnnotationKind::Service]);
+        assert_eq!(classes["Baz"].order, 2);
+        // Qualified names don't make up namespaces
+        assert_eq!(diagram.class_count(), 3);
+
+        // A later declaration adds to the class instead of replacing it
+        let source = "classDiagram\nFoo : +x int\nclass Bar\nclass Foo {\n  +y int\n}\n";
+        let diagram = parse_mermaid(source).unwrap();
+        let classes = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes;
+        assert_eq!(classes["Foo"].members.len(), 2);
+        assert_eq!(classes["Foo"].order, 0);
+    }
+
+    #[test]
+    fn test_parse_with_options_line_continuation() {
+        let source = "classDiagram\nclass Map {\n  +put(K key, \\\n       V value) void\n  +size() int\n}\nMap : +merge(Map other, \\\r\n  bool overwrite)\n";
+        let options = ParseOptions {
+            allow_line_continuation: true,
+            ..Default::default()
+        };
+        let diagram = parse_with_options(source, &options).unwrap();
+        let members = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes["Map"].members;
+        assert_eq!(members.len(), 3);
+        let types::Member::Method(put) = &members[0] else {
+            panic!("put should be a method");
+        };
+        assert_eq!(put.parameters.len(), 2);
+
+        assert!(parse_mermaid(source).is_err());
+    }
+
+    #[test]
+    fn test_parse_with_options_spans() {
+        let source = "---\ntitle: Zoo\n---\nclassDiagram\nclass Animal {\n  +name: String\n  +eat() void\n}\nnamespace Pets {\n  class Dog\n  Dog : +bark()\n}\nAnimal <|-- Pets::Dog : is a\nnote for Animal \"alive\"\nAnimal : +age: int\n";
+        let options = ParseOptions {
+            spans: true,
+            ..Default::default()
+        };
+        let diagram = parse_with_options(source, &options).unwrap();
+        let text = |span: Option<types::Span>| {
+            let span = span.expect("The span should be recorded");
+            &source[span.start..span.end]
+        };
+
+        let animal = diagram.find_class("Animal").unwrap();
+        assert_eq!(
+            text(animal.span),
+            "class Animal {\n  +name: String\n  +eat() void\n}"
+        );
+        assert_eq!(text(animal.members[0].span()), "+name: String");
+        assert_eq!(text(animal.members[1].span()), "+eat() void");
+        assert_eq!(text(animal.members[2].span()), "Animal : +age: int");
+
+        let dog = diagram.find_class("Pets::Dog").unwrap();
+        assert_eq!(text(dog.span), "class Dog");
+        assert_eq!(text(dog.members[0].span()), "Dog : +bark()");
+
+        assert_eq!(
+            text(diagram.relations[0].span),
+            "Animal <|-- Pets::Dog : is a"
+        );
+        assert_eq!(text(diagram.notes[0].span), "note for Animal \"alive\"");
+
+        // Each link of a chain spans its own two classes
+        let diagram = parse_with_options("classDiagram\nA --> B --> C\n", &options).unwrap();
+        let spans: Vec<_> = diagram.relations.iter().map(|rl| rl.span).collect();
+        assert_eq!(
+            spans,
+            [
+                Some(types::Span { start: 13, end: 20 }),
+                Some(types::Span { start: 19, end: 26 })
+            ]
+        );
+
+        // Off by default
+        let diagram = parse_mermaid(source).unwrap();
+        assert_eq!(diagram.find_class("Animal").unwrap().span, None);
+        assert_eq!(diagram.relations[0].span, None);
+    }
+
+    #[test]
+    fn test_parse_mermaid_crlf() {
+        let source = "classDiagram\r\nclass A {\r\n  <<interface>>\r\n  %% a comment\r\n  +id: int\r\n  ???\r\n  +run() void\r\n}\r\nclass B\r\nA --> B : uses\r\n";
+        let diagram = parse_mermaid(source).unwrap();
+        assert_eq!(diagram.class_count(), 2);
+        assert_eq!(diagram.relations[0].label, Some("uses".into()));
+        let class = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes["A"];
+        assert_eq!(class.annotations, [AnnotationKind::Interface]);
+        assert_eq!(class.members.len(), 2);
+        assert_eq!(
+            diagram,
+            parse_mermaid(&source.replace("\r\n", "\n")).unwrap()
+        );
+
+        let source = "classDiagram\r\nnamespace N {\r\n  %% a comment\r\n  ???\r\n  class C {\r\n    +x: int\r\n  }\r\n  class D\r\n}\r\nclass E\r\nC <|-- D\r\n";
+        let diagram = parse_mermaid(source).unwrap();
+        assert_eq!(diagram.class_count(), 3);
+        assert_eq!(diagram.namespaces["N"].classes.len(), 2);
+        assert_eq!(
+            diagram,
+            parse_mermaid(&source.replace("\r\n", "\n")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_mermaid_unterminated_body() {
+        assert!(matches!(
+            parse_mermaid("classDiagram\nclass A {\n  +x: int\n  ???\n"),
+            Err(MermaidParseError::ExpectedStmt { offset: 13, .. })
+        ));
+        assert!(matches!(
+            parse_mermaid("classDiagram\r\nnamespace N {\r\n  class A\r\n"),
+            Err(MermaidParseError::ExpectedStmt { offset: 14, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_mermaid_consecutive_relations() {
+        let mut source = String::from("classDiagram\n");
+        for i in 0..50 {
+            source.push_str(&format!("C{}-->C{}\n", i, i + 1));
+        }
+
+        let (diagram, rest) = parse_partial(&source);
+        assert!(rest.is_empty(), "Nothing should be left over");
+        assert_eq!(diagram.relations.len(), 50);
+        for (i, relation) in diagram.relations.iter().enumerate() {
+            assert_eq!(relation.tail, format!("C{}", i));
+            assert_eq!(relation.head, format!("C{}", i + 1));
+            assert_eq!(relation.label, None);
+        }
+    }
+
+    #[test]
+    fn test_parse_mermaid_class_order() {
+        let diagram = parse_mermaid(
+            "classDiagram\nclass Zebra\nnamespace Zoo {\nclass Lion\nclass Bear\n}\nclass Apple\n",
+        )
+        .unwrap();
+        let order = |ns: &str, class: &str| diagram.namespaces[ns].classes[class].order;
+        assert_eq!(order(types::DEFAULT_NAMESPACE, "Zebra"), 0);
+        assert_eq!(order("Zoo", "Lion"), 1);
+        assert_eq!(order("Zoo", "Bear"), 2);
+        assert_eq!(order(types::DEFAULT_NAMESPACE, "Apple"), 3);
+    }
+
+    #[test]
+    fn test_parse_mermaid_class_list() {
+        let diagram = parse_mermaid("classDiagram\nclass A, B,C\nclass D\n")
+            .expect("Failed to parse class list");
+        let classes = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes;
+        assert_eq!(classes.len(), 4);
+        assert_eq!(classes["A"].order, 0);
+        assert_eq!(classes["B"].order, 1);
+        assert_eq!(classes["C"].order, 2);
+        assert_eq!(classes["D"].order, 3);
+    }
+
+    #[test]
+    fn test_parse_mermaid_comma_separated_members() {
+        let diagram = parse_mermaid("classDiagram\nclass Dog\nDog : +bark() void, +age: int\n")
+            .expect("Failed to parse comma separated members");
+        let dog = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes["Dog"];
+        assert_eq!(dog.members.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_mermaid_member_annotation() {
+        let diagram = parse_mermaid("classDiagram\nclass Foo\nFoo : <<service>>\nFoo : +run()\n")
+            .expect("Failed to parse member-line annotation");
+        let foo = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes["Foo"];
+        assert_eq!(foo.annotations, [AnnotationKind::Service]);
+        assert_eq!(foo.members.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_mermaid_standalone_annotation() {
+        let diagram = parse_mermaid(
+            "classDiagram\nclass Shape\n<<interface>> Shape\n<<enumeration>> Color\n",
+        )
+        .expect("Failed to parse standalone annotation");
+        let classes = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes;
+        assert_eq!(classes["Shape"].annotations, [AnnotationKind::Interface]);
+        // Like a member line, the annotation declares a class it hasn't seen yet
+        assert_eq!(classes["Color"].annotations, [AnnotationKind::Enumeration]);
+    }
+
     #[test]
     fn test_direction_stmt() {
         // Test all direction values
@@ -284,6 +1282,150 @@ mod tests {
         assert_eq!(dir, types::Direction::LeftRight);
     }
 
+    #[test]
+    fn test_parse_mermaid_namespaced_members() {
+        let source = "classDiagram\nnamespace Animals {\n  class Dog\n}\nclass Dog\nAnimals::Dog : +bark() void, +name: String\nAnimals::Dog : <<entity>>\nDog : +id: int\nAnimals::Cat : +meow()\n";
+        let diagram = parse_mermaid(source).expect("Failed to parse");
+
+        let dog = &diagram.namespaces["Animals"].classes["Dog"];
+        assert_eq!(dog.members.len(), 2);
+        assert_eq!(dog.members[0].name(), "bark");
+        assert_eq!(dog.annotations, [AnnotationKind::Other("entity".into())]);
+
+        // The plain name still means the class in the default namespace
+        let dog = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes["Dog"];
+        assert_eq!(dog.members.len(), 1);
+        assert_eq!(dog.members[0].name(), "id");
+
+        // A qualified name doesn't declare the class, so members for `Animals::Cat` are dropped,
+        // or rejected in strict mode
+        assert!(diagram.find_class("Animals::Cat").is_none());
+        let Err(MermaidParseError::InvalidMember { offset, .. }) = parse_mermaid_strict(source)
+        else {
+            panic!("Members for an unknown qualified class should be rejected");
+        };
+        assert_eq!(&source[offset..], "Animals::Cat : +meow()\n");
+    }
+
+    #[test]
+    fn test_parse_with_options() {
+        let source = "classDiagram\nclass A {\n  +++garbage\n}\ndirection down\n";
+        let mut options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+
+        let diagram = parse_with_options(source, &options).expect("Failed to parse");
+        assert_eq!(diagram.direction, Some(Direction::TopBottom));
+        assert!(
+            diagram.namespaces[types::DEFAULT_NAMESPACE].classes["A"]
+                .members
+                .is_empty()
+        );
+
+        options.strict = true;
+        assert!(matches!(
+            parse_with_options(source, &options),
+            Err(MermaidParseError::InvalidMember { offset: 25, .. })
+        ));
+
+        // Without `lenient` the direction alias isn't understood
+        options = ParseOptions::default();
+        assert!(matches!(
+            parse_with_options(source, &options),
+            Err(MermaidParseError::ExpectedStmt { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_mermaid_strict_members() {
+        let source = "classDiagram\nclass A {\n  +name: String\n  +++garbage\n}\n";
+
+        // By default the bad line is dropped
+        let diagram = parse_mermaid(source).expect("Failed to parse");
+        let class = &diagram.namespaces[types::DEFAULT_NAMESPACE].classes["A"];
+        assert_eq!(class.members.len(), 1);
+
+        let Err(MermaidParseError::InvalidMember { offset, .. }) = parse_mermaid_strict(source)
+        else {
+            panic!("Strict mode should reject the member line");
+        };
+        assert!(source[offset..].starts_with("+++garbage\n"));
+
+        // Also inside a namespace
+        let source = "classDiagram\nnamespace N {\n  class B {\n    +++garbage\n  }\n}\n";
+        assert!(parse_mermaid(source).is_ok());
+        let Err(MermaidParseError::InvalidMember { offset, .. }) = parse_mermaid_strict(source)
+        else {
+            panic!("Strict mode should reject the member line");
+        };
+        assert!(source[offset..].starts_with("+++garbage\n"));
+
+        let source = "classDiagram\nclass A {\n  %% comment\n  <<interface>>\n  +run() void\n}\n";
+        assert_eq!(
+            parse_mermaid_strict(source).unwrap(),
+            parse_mermaid(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_mermaid_lenient_direction() {
+        let aliases = [
+            ("up", types::Direction::BottomTop),
+            ("down", types::Direction::TopBottom),
+            ("left", types::Direction::RightLeft),
+            ("right", types::Direction::LeftRight),
+        ];
+        for (alias, expected) in aliases {
+            let source = format!("classDiagram\ndirection {alias}\nclass A\n");
+            let diagram = parse_mermaid_lenient(&source).expect("Failed to parse alias");
+            assert_eq!(diagram.direction, Some(expected), "direction {alias}");
+
+            // Only the lenient parser knows the aliases
+            assert!(parse_mermaid(&source).is_err());
+
+            // The serializer writes the canonical code back out
+            let serialized = crate::serializer::serialize_diagram(&diagram);
+            assert_eq!(
+                parse_mermaid(&serialized).unwrap().direction,
+                Some(expected)
+            );
+        }
+
+        // The two letter codes still work
+        let diagram = parse_mermaid_lenient("classDiagram\ndirection LR\n").unwrap();
+        assert_eq!(diagram.direction, Some(types::Direction::LeftRight));
+    }
+
+    #[test]
+    fn test_parse_mermaid_direction_case() {
+        let cases = [
+            ("td", types::Direction::TopBottom, "TB"),
+            ("Lr", types::Direction::LeftRight, "LR"),
+            ("bT", types::Direction::BottomTop, "BT"),
+            ("TD", types::Direction::TopBottom, "TB"),
+        ];
+        for (written, expected, canonical) in cases {
+            let source = format!("classDiagram\ndirection {written}\nclass A\n");
+            let diagram = parse_mermaid(&source).expect("Failed to parse direction");
+            assert_eq!(diagram.direction, Some(expected), "direction {written}");
+
+            let serialized = crate::serializer::serialize_diagram(&diagram);
+            assert!(
+                serialized.contains(&format!("direction {canonical}\n")),
+                "{serialized}"
+            );
+        }
+
+        // Inside a namespace too
+        let diagram = parse_mermaid("classDiagram\nnamespace N {\ndirection rl\nclass A\n}\n")
+            .expect("Failed to parse namespace direction");
+        assert_eq!(
+            diagram.namespaces["N"].direction,
+            Some(types::Direction::RightLeft)
+        );
+    }
+
     #[test]
     fn test_note_stmt() {
         // Test general note (not attached to a class)
@@ -305,6 +1447,44 @@ mod tests {
         assert!(rem.is_empty());
         assert_eq!(note.text, "Vehicles are fast");
         assert_eq!(note.target_class, Some("Vehicle".into()));
+        assert_eq!(note.placement, None);
+
+        // Notes placed next to a class
+        let (rem, Stmt::Note(note)) =
+            note_stmt("note left of A \"x\"").expect("Failed to parse placed note")
+        else {
+            panic!("Expected Note statement");
+        };
+        assert!(rem.is_empty());
+        assert_eq!(note.text, "x");
+        assert_eq!(note.target_class, Some("A".into()));
+        assert_eq!(note.placement, Some(types::NotePlacement::LeftOf));
+
+        let (_, Stmt::Note(note)) = note_stmt("note right  of B \"y\"").unwrap() else {
+            panic!("Expected Note statement");
+        };
+        assert_eq!(note.placement, Some(types::NotePlacement::RightOf));
+
+        let (_, Stmt::Note(note)) = note_stmt("note over C \"z\"").unwrap() else {
+            panic!("Expected Note statement");
+        };
+        assert_eq!(note.target_class, Some("C".into()));
+        assert_eq!(note.placement, Some(types::NotePlacement::Over));
+
+        // A backslash only escapes a quote or another backslash
+        for (source, text) in [
+            (r#"note "C:\dir""#, r"C:\dir"),
+            (r#"note "C:\dir\\""#, r"C:\dir\"),
+            (r#"note "say \"hi\"""#, r#"say "hi""#),
+            (r#"note "a\\\"b""#, r#"a\"b"#),
+        ] {
+            let (rem, Stmt::Note(note)) = note_stmt(source).expect(source) else {
+                panic!("Expected Note statement");
+            };
+            assert!(rem.is_empty(), "{source}");
+            assert_eq!(note.text, text, "{source}");
+        }
+        assert!(note_stmt(r#"note "C:\dir\""#).is_err());
 
         // Test note with longer text
         let (rem, Stmt::Note(note)) =
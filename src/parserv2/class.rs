@@ -6,32 +6,100 @@ use nom::{
     bytes::complete::{tag, take_while1},
     character::complete::{char, multispace0, space1},
     combinator::opt,
-    sequence::{delimited, preceded},
+    sequence::{delimited, preceded, terminated},
 };
 
-use crate::types::{Attribute, Class, Member, Method, Parameter, TypeNotation, Visibility};
+use crate::types::{
+    AnnotationKind, Attribute, Class, Member, Method, Parameter, TypeNotation, Visibility,
+};
 
-use super::{IResult, Stmt};
+use super::{IResult, MermaidParseError, ParseOptions, Stmt};
 
 pub fn class_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'source>> {
-    use nom::{
-        bytes::complete::{take_until, take_while},
-        character::complete::{char, line_ending},
+    class_stmt_with(s, &ParseOptions::default())
+}
+
+/// Like [`class_stmt`], but a line in the class body that isn't a member is a
+/// [`MermaidParseError::InvalidMember`] failure instead of being skipped.
+pub fn class_stmt_strict<'source>(s: &'source str) -> IResult<&'source str, Stmt<'source>> {
+    let options = ParseOptions {
+        strict: true,
+        ..Default::default()
     };
+    class_stmt_with(s, &options)
+}
 
-    let (s, name) = preceded((multispace0, tag("class"), space1), class_name).parse_complete(s)?;
+/// [`class_stmt`] using the given options. Spans are relative to the start of `s`.
+pub fn class_stmt_with<'source>(
+    s: &'source str,
+    options: &ParseOptions,
+) -> IResult<&'source str, Stmt<'source>> {
+    use nom::character::complete::char;
+
+    let input = s;
+    let span = |rest: &str| options.spans.then(|| super::span_in(input, input, rest));
+
+    let (s, _) = (multispace0, tag("class"), space1).parse_complete(s)?;
+    let name_start = s;
+    let (s, head) = class_head(s)?;
+
+    // `class A, B~T~, C` declares several bare classes at once, each with its own suffixes. The
+    // first one spans from the `class` keyword, the others just their own part of the list.
+    let mut classes = vec![(span(s), head)];
+    let mut s = s;
+    while let Ok((rest, _)) = char::<_, MermaidParseError>(',').parse(s) {
+        let (rest, head) = class_head(rest)?;
+        classes.push((
+            options.spans.then(|| super::span_in(input, &s[1..], rest)),
+            head,
+        ));
+        s = rest;
+    }
+    if classes.len() > 1 {
+        let classes = classes
+            .into_iter()
+            .map(|(span, (name, generic, label, css_class))| Class {
+                name: Cow::Borrowed(name),
+                generic,
+                label,
+                css_class,
+                annotations: Vec::new(),
+                members: Vec::new(),
+                order: 0,
+                span,
+            })
+            .collect();
+        return Ok((s, Stmt::Classes(classes)));
+    }
+    let (_, (name, generic, label, css_class)) = classes.pop().expect("There is always one");
+
+    // `class Shape <<interface>> {` annotates the class inline, as long as the annotation is on
+    // the same line. `class_name` already ate the whitespace in between.
+    let consumed = &name_start[..name_start.len() - s.len()];
+    let same_line = !consumed[consumed.trim_end().len()..].contains(['\r', '\n']);
+    let (s, inline_annotation) = if same_line {
+        opt(class_annotation).parse(s)?
+    } else {
+        (s, None)
+    };
+    let inline_annotation = inline_annotation.map(AnnotationKind::from);
 
     let (s, _) = multispace0.parse(s)?;
 
     // Check if there's an opening brace - if not, this is a bare class declaration
-    if let Err(_) = char::<_, nom::error::Error<_>>('{').parse(s) {
+    if char::<_, nom::error::Error<_>>('{').parse(s).is_err() {
         // Bare class declaration - just return empty class
         return Ok((
             s,
             Stmt::Class(Class {
                 name: Cow::Borrowed(name),
-                annotation: None,
+                generic,
+                label,
+                css_class,
+                annotations: inline_annotation.into_iter().collect(),
                 members: Vec::new(),
+                order: 0,
+                span: span(s),
             }),
         ));
     }
@@ -42,6 +110,7 @@ pub fn class_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'sourc
 
     // Parse members, handling comments and whitespace
     let mut members = Vec::new();
+    let mut annotations: Vec<_> = inline_annotation.into_iter().collect();
     let mut s = s;
 
     loop {
@@ -58,26 +127,41 @@ pub fn class_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'sourc
         }
 
         // Check for comment line (starts with %%)
-        if let Ok((s_new, _)) = tag::<_, _, nom::error::Error<_>>("%%").parse(s) {
-            // Skip the rest of the line
-            let (s_new, _) = take_while(|c| c != '\n' && c != '\r').parse(s_new)?;
+        if let Ok((s_new, _)) = super::comment(s) {
+            s = s_new;
+            continue;
+        }
+
+        // Annotation line like <<interface>>
+        if let Ok((s_new, found)) = class_annotation(s) {
+            let found = AnnotationKind::from(found);
+            if !annotations.contains(&found) {
+                annotations.push(found);
+            }
             s = s_new;
             continue;
         }
 
         // Try to parse a member
-        match class_member_stmt(s) {
-            Ok((s_new, member)) => {
+        match class_member_stmt_with(s, options) {
+            Ok((s_new, mut member)) => {
+                if options.spans {
+                    *super::member_span_mut(&mut member) = Some(super::span_in(input, s, s_new));
+                }
                 members.push(member);
                 s = s_new;
             }
+            Err(_) if options.strict => {
+                return Err(nom::Err::Failure(MermaidParseError::InvalidMember {
+                    offset: s.len(),
+                    line: 0,
+                    text: String::new(),
+                }));
+            }
             Err(_) => {
                 // If we can't parse a member, skip to the next line
-                if let Ok((s_new, _)) = take_while::<_, _, nom::error::Error<_>>(|c| c != '\n' && c != '\r').parse(s) {
-                    s = s_new;
-                } else {
-                    break;
-                }
+                let (s_new, _) = super::skip_line(s)?;
+                s = s_new;
             }
         }
     }
@@ -86,21 +170,93 @@ pub fn class_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'sourc
         s,
         Stmt::Class(Class {
             name: Cow::Borrowed(name),
-            annotation: None,
+            generic,
+            label,
+            css_class,
+            annotations,
             members,
+            order: 0,
+            span: span(s),
         }),
     ))
 }
 
 pub fn class_member_stmt<'source>(s: &'source str) -> IResult<&'source str, Member<'source>> {
+    class_member_stmt_with(s, &ParseOptions::default())
+}
+
+/// [`class_member_stmt`] using the given options
+pub fn class_member_stmt_with<'source>(
+    s: &'source str,
+    options: &ParseOptions,
+) -> IResult<&'source str, Member<'source>> {
     // Try to parse as a method first (methods have parentheses), then fallback to attribute
     alt((
-        |s| class_method(s).map(|(rem, method)| (rem, Member::Method(method))),
+        |s| class_method_with(s, options).map(|(rem, method)| (rem, Member::Method(method))),
         |s| class_attribute(s).map(|(rem, attr)| (rem, Member::Attribute(attr))),
     ))
     .parse(s)
 }
 
+/// One or more members separated by commas, as in `Dog : +bark() void, +age: int`. Commas inside
+/// parameter lists and generics belong to the member they're in.
+pub fn class_member_list<'source>(s: &'source str) -> IResult<&'source str, Vec<Member<'source>>> {
+    class_member_list_with(s, &ParseOptions::default())
+}
+
+/// [`class_member_list`] using the given options
+pub fn class_member_list_with<'source>(
+    s: &'source str,
+    options: &ParseOptions,
+) -> IResult<&'source str, Vec<Member<'source>>> {
+    use nom::{character::complete::space0, multi::separated_list1};
+
+    separated_list1((space0, char(','), space0), |s| {
+        class_member_stmt_with(s, options)
+    })
+    .parse(s)
+}
+
+/// `:::name`, returning just `name`
+fn css_class_suffix(s: &str) -> IResult<&str, &str> {
+    preceded(
+        tag(":::"),
+        take_while1(|c: char| is_name_char(c) || c == '-'),
+    )
+    .parse(s)
+}
+
+/// The display text in `class animalClass["Animal"]`, returning what's between the quotes
+pub fn class_label(s: &str) -> IResult<&str, &str> {
+    use nom::bytes::complete::take_while;
+
+    let (s, label) = delimited(
+        tag("[\""),
+        take_while(|c| c != '"' && c != '\r' && c != '\n'),
+        tag("\"]"),
+    )
+    .parse(s)?;
+    let (s, _) = nom::character::complete::space0.parse(s)?;
+    Ok((s, label))
+}
+
+/// An annotation such as `<<interface>>`, returning the text between the angle brackets
+pub fn class_annotation(s: &str) -> IResult<&str, &str> {
+    use nom::bytes::complete::is_not;
+
+    delimited(tag("<<"), is_not(">\r\n"), tag(">>"))
+        .map(str::trim)
+        .parse(s)
+}
+
+/// `<<annotation>> ClassName` on a line of its own, returning the class name and the annotation
+pub fn annotation_stmt(s: &str) -> IResult<&str, (&str, &str)> {
+    let (s, annotation) = class_annotation(s)?;
+    let (s, _) = space1.parse(s)?;
+    let (s, name) = class_name(s)?;
+    Ok((s, (name, annotation)))
+}
+
 pub fn class_visibility(s: &str) -> IResult<&str, Visibility> {
     use nom::character::complete::one_of;
 
@@ -149,12 +305,8 @@ pub fn class_attribute<'source>(s: &'source str) -> IResult<&'source str, Attrib
     let (s, _) = space0.parse(s)?;
 
     // Try to parse as postfix notation (name: Type) or prefix notation (Type name) or just name
-    // First, get the first identifier
-    let (s, first_token) = recognize(pair(
-        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-        take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
-    ))
-    .parse(s)?;
+    // First, get the first identifier, which may be a generic type in prefix notation
+    let (s, first_token) = type_token(s)?;
 
     let (s, _) = space0.parse(s)?;
 
@@ -164,31 +316,29 @@ pub fn class_attribute<'source>(s: &'source str) -> IResult<&'source str, Attrib
     if has_colon.is_some() {
         // Postfix notation: name: Type
         let (s, _) = space0.parse(s)?;
-        let (s, type_token) = opt(recognize(pair(
-            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
-        )))
-        .parse(s)?;
+        let (s, type_token) = opt(postfix_type).parse(s)?;
+        let type_notation = if type_token.is_some() {
+            TypeNotation::Postfix
+        } else {
+            TypeNotation::None
+        };
 
         Ok((
             s,
             Attribute {
                 visibility,
                 name: Cow::Borrowed(first_token),
-                data_type: type_token.map(Cow::Borrowed),
+                data_type: type_token,
                 is_static,
-                type_notation: if type_token.is_some() {
-                    TypeNotation::Postfix
-                } else {
-                    TypeNotation::None
-                },
+                type_notation,
+                span: None,
             },
         ))
     } else {
         // Check if there's a second token (prefix notation: Type name)
         let (s, second_token) = opt(recognize(pair(
-            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+            take_while1(is_name_char),
+            take_while(|c: char| is_name_char(c) || c == '-'),
         )))
         .parse(s)?;
 
@@ -202,6 +352,7 @@ pub fn class_attribute<'source>(s: &'source str) -> IResult<&'source str, Attrib
                     data_type: Some(Cow::Borrowed(first_token)),
                     is_static,
                     type_notation: TypeNotation::Prefix,
+                    span: None,
                 },
             ))
         } else {
@@ -214,6 +365,7 @@ pub fn class_attribute<'source>(s: &'source str) -> IResult<&'source str, Attrib
                     data_type: None,
                     is_static,
                     type_notation: TypeNotation::None,
+                    span: None,
                 },
             ))
         }
@@ -221,11 +373,20 @@ pub fn class_attribute<'source>(s: &'source str) -> IResult<&'source str, Attrib
 }
 
 pub fn class_method<'source>(s: &'source str) -> IResult<&'source str, Method<'source>> {
+    class_method_with(s, &ParseOptions::default())
+}
+
+/// [`class_method`] using the given options. With [`ParseOptions::allow_line_continuation`] the
+/// parameter list may go on over several lines, each but the last ending in a `\`.
+pub fn class_method_with<'source>(
+    s: &'source str,
+    options: &ParseOptions,
+) -> IResult<&'source str, Method<'source>> {
     use nom::{
         bytes::complete::take_while,
-        character::complete::{char, space0},
+        character::complete::{char, one_of, space0},
         combinator::recognize,
-        multi::separated_list0,
+        multi::{many0, separated_list0},
         sequence::pair,
     };
 
@@ -237,38 +398,17 @@ pub fn class_method<'source>(s: &'source str) -> IResult<&'source str, Method<'s
 
     let (s, _) = space0.parse(s)?;
 
-    // Optional static modifier ($)
-    let (s, is_static) = opt(|s| {
-        let (s, _) = char('$').parse(s)?;
-        let (s, _) = space0.parse(s)?;
-        Ok((s, true))
-    })
-    .parse(s)?;
-    let is_static = is_static.unwrap_or(false);
-
-    let (s, _) = space0.parse(s)?;
-
-    // Optional abstract modifier (*)
-    let (s, is_abstract) = opt(|s| {
-        let (s, _) = char('*').parse(s)?;
-        let (s, _) = space0.parse(s)?;
-        Ok((s, true))
-    })
-    .parse(s)?;
-    let is_abstract = is_abstract.unwrap_or(false);
-
-    let (s, _) = space0.parse(s)?;
+    // Optional static ($) and abstract (*) modifiers, in either order
+    let (s, classifiers) = many0(terminated(one_of("$*"), space0)).parse(s)?;
+    let is_static = classifiers.contains(&'$');
+    let is_abstract = classifiers.contains(&'*');
 
     // Check if there's a return type before the method name (prefix notation)
     // We need to look ahead to see if there's an identifier followed by '('
     // Let's try to parse: [Type] name(params) [ReturnType]
 
-    // Try to get first token
-    let (s, first_token) = recognize(pair(
-        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-        take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
-    ))
-    .parse(s)?;
+    // Try to get first token, which may be a generic return type
+    let (s, first_token) = type_token(s)?;
 
     let (s, _) = space0.parse(s)?;
 
@@ -281,8 +421,8 @@ pub fn class_method<'source>(s: &'source str) -> IResult<&'source str, Method<'s
     } else {
         // first_token might be a return type, get the next token as method name
         let (s, name_token) = recognize(pair(
-            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+            take_while1(is_name_char),
+            take_while(|c: char| is_name_char(c) || c == '-'),
         ))
         .parse(s)?;
 
@@ -293,23 +433,18 @@ pub fn class_method<'source>(s: &'source str) -> IResult<&'source str, Method<'s
     };
 
     // Parse parameters
-    let (s, _) = space0.parse(s)?;
-    let (s, parameters) = separated_list0(
-        (space0, char(','), space0),
-        class_method_param,
-    )
-    .parse(s)?;
+    let gap = |s| param_gap(s, options.allow_line_continuation);
+    let (s, _) = gap(s)?;
+    let (s, parameters) = separated_list0((gap, char(','), gap), class_method_param).parse(s)?;
 
-    let (s, _) = space0.parse(s)?;
+    // Tolerate a trailing comma, as in `method(a, )` or `method(,)`
+    let (s, _) = gap(s)?;
+    let (s, _) = opt((char(','), gap)).parse(s)?;
     let (s, _) = char(')').parse(s)?;
     let (s, _) = space0.parse(s)?;
 
     // Check for postfix return type
-    let (s, postfix_return_type) = opt(recognize(pair(
-        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-        take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
-    )))
-    .parse(s)?;
+    let (s, postfix_return_type) = opt(type_token).parse(s)?;
 
     // Determine return type and notation
     let (return_type, return_type_notation) = if let Some(prefix_type) = prefix_return_type {
@@ -330,10 +465,24 @@ pub fn class_method<'source>(s: &'source str) -> IResult<&'source str, Method<'s
             is_static,
             is_abstract,
             return_type_notation,
+            span: None,
         },
     ))
 }
 
+/// Whitespace in a parameter list, including `\` line continuations if `continuation` is set
+fn param_gap(s: &str, continuation: bool) -> IResult<&str, ()> {
+    use nom::character::complete::{line_ending, space0};
+
+    let (mut s, _) = space0.parse(s)?;
+    while continuation
+        && let Ok((rest, _)) = (char::<_, MermaidParseError>('\\'), line_ending, space0).parse(s)
+    {
+        s = rest;
+    }
+    Ok((s, ()))
+}
+
 pub fn class_method_param<'source>(
     s: &'source str,
 ) -> IResult<&'source str, Parameter<'source>> {
@@ -346,69 +495,247 @@ pub fn class_method_param<'source>(
 
     let (s, _) = space0.parse(s)?;
 
-    // Get first identifier
-    let (s, first_token) = recognize(pair(
-        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-        take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
-    ))
-    .parse(s)?;
+    // Get first identifier, which may be a generic type in prefix notation
+    let (s, first_token) = type_token(s)?;
 
     let (s, _) = space0.parse(s)?;
 
     // Check if there's a colon (postfix notation: name: Type)
     let (s, has_colon) = opt(char(':')).parse(s)?;
 
-    if has_colon.is_some() {
+    let (s, (name, data_type, type_notation)) = if has_colon.is_some() {
         // Postfix notation
         let (s, _) = space0.parse(s)?;
-        let (s, type_token) = opt(recognize(pair(
-            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
-        )))
-        .parse(s)?;
-
-        Ok((
-            s,
-            Parameter {
-                name: Cow::Borrowed(first_token),
-                data_type: type_token.map(Cow::Borrowed),
-                type_notation: if type_token.is_some() {
-                    TypeNotation::Postfix
-                } else {
-                    TypeNotation::None
-                },
-            },
-        ))
+        let (s, type_token) = opt(postfix_type).parse(s)?;
+        let type_notation = if type_token.is_some() {
+            TypeNotation::Postfix
+        } else {
+            TypeNotation::None
+        };
+        (s, (first_token, type_token, type_notation))
     } else {
         // Check for second token (prefix notation: Type name)
         let (s, second_token) = opt(recognize(pair(
-            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+            take_while1(is_name_char),
+            take_while(|c: char| is_name_char(c) || c == '-'),
         )))
         .parse(s)?;
 
-        if let Some(name_token) = second_token {
+        match second_token {
             // Prefix notation: Type name
-            Ok((
+            Some(name_token) => (
                 s,
-                Parameter {
-                    name: Cow::Borrowed(name_token),
-                    data_type: Some(Cow::Borrowed(first_token)),
-                    type_notation: TypeNotation::Prefix,
-                },
-            ))
-        } else {
+                (
+                    name_token,
+                    Some(Cow::Borrowed(first_token)),
+                    TypeNotation::Prefix,
+                ),
+            ),
             // Just a name with no type
-            Ok((
-                s,
-                Parameter {
-                    name: Cow::Borrowed(first_token),
-                    data_type: None,
-                    type_notation: TypeNotation::None,
-                },
-            ))
+            None => (s, (first_token, None, TypeNotation::None)),
+        }
+    };
+
+    // An optional default value, `x: int = 5`
+    let (s, default_value) = opt(preceded((space0, char('='), space0), param_default)).parse(s)?;
+
+    Ok((
+        s,
+        Parameter {
+            name: Cow::Borrowed(name),
+            data_type,
+            type_notation,
+            default_value: default_value.map(Cow::Borrowed),
+        },
+    ))
+}
+
+/// The default value of a parameter as written: a quoted string (quotes included) or anything
+/// up to the next `,`, `)` or whitespace
+fn param_default(s: &str) -> IResult<&str, &str> {
+    use nom::{bytes::complete::take_while, combinator::recognize};
+
+    alt((
+        recognize(delimited(
+            char('"'),
+            take_while(|c| c != '"' && c != '\r' && c != '\n'),
+            char('"'),
+        )),
+        take_while1(|c: char| c != ',' && c != ')' && !c.is_whitespace()),
+    ))
+    .parse(s)
+}
+
+/// Parse a type: an identifier with an optional generic suffix (`List~int~`, `Map~K, V~`,
+/// `Map~String, List~int~~`). Without a suffix this is just an identifier.
+pub fn type_token(s: &str) -> IResult<&str, &str> {
+    use nom::{bytes::complete::take_while, combinator::recognize, sequence::pair};
+
+    recognize(pair(
+        pair(
+            take_while1(is_name_char),
+            take_while(|c: char| is_name_char(c) || c == '-'),
+        ),
+        opt(generic_suffix),
+    ))
+    .parse(s)
+}
+
+/// Parse the type after a colon. On top of [`type_token`] this accepts an angle bracket generic
+/// that may have spaces inside, like `Map < K , V >`. Whitespace is normalized so that example is
+/// stored as `Map<K, V>`; a type already written that way is borrowed from the source.
+pub fn postfix_type(s: &str) -> IResult<&str, Cow<'_, str>> {
+    use nom::character::complete::space0;
+
+    let (rest, base) = type_token(s)?;
+
+    let angle = (space0, angle_suffix).parse(rest);
+    let Ok((rest, _)) = angle else {
+        return Ok((rest, Cow::Borrowed(base)));
+    };
+
+    let raw = &s[..s.len() - rest.len()];
+    let mut normalized = String::with_capacity(raw.len());
+    let mut pending_space = false;
+    for c in raw.chars() {
+        match c {
+            c if c.is_whitespace() => pending_space = true,
+            '<' | '>' | ',' => {
+                normalized.push(c);
+                if c == ',' {
+                    normalized.push(' ');
+                }
+                pending_space = false;
+            }
+            c => {
+                if pending_space && !normalized.ends_with(['<', ' ']) {
+                    normalized.push(' ');
+                }
+                normalized.push(c);
+                pending_space = false;
+            }
         }
     }
+
+    if normalized == raw {
+        Ok((rest, Cow::Borrowed(raw)))
+    } else {
+        Ok((rest, Cow::Owned(normalized)))
+    }
+}
+
+/// Parse a balanced `<...>` generic suffix that closes before the end of the line
+fn angle_suffix(s: &str) -> IResult<&str, &str> {
+    let (rest, _) = char('<').parse(s)?;
+
+    let mut depth = 1;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '\n' | '\r' => break,
+            '<' => depth += 1,
+            '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    let end = 1 + i + c.len_utf8();
+                    return Ok((&s[end..], &s[..end]));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Err(nom::Err::Error(MermaidParseError::Nom(
+        nom::error::ErrorKind::Char,
+    )))
+}
+
+/// Whether `c` can be part of a name: a letter or digit in any script, `_`, or a combining mark,
+/// so an accent written after its letter (`e\u{301}`) doesn't cut a name short. Other symbols and
+/// punctuation (`→`, `«`, emoji) need backticks, as they do in ASCII.
+pub(crate) fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || is_combining_mark(c)
+}
+
+/// The Unicode blocks of combining marks, along with the zero width (non-)joiner some scripts
+/// need inside words
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c,
+        '\u{300}'..='\u{36F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{200C}'..='\u{200D}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+/// A class name with the suffixes that may follow it in a `class` statement: the `~generic~`
+/// (without its tildes), the `["label"]` and the `:::css` class
+type ClassHead<'source> = (
+    &'source str,
+    Option<Cow<'source, str>>,
+    Option<Cow<'source, str>>,
+    Option<Cow<'source, str>>,
+);
+
+fn class_head(s: &str) -> IResult<&str, ClassHead<'_>> {
+    let name_start = s;
+    let (s, name) = class_name(s)?;
+
+    // `class Square~Shape~` gives the class a type parameter. It has to follow the name directly,
+    // or the closing backtick of an escaped name.
+    let written = &name_start[..name_start.len() - s.len()];
+    let (s, generic) = if written.ends_with(name) || written.ends_with('`') {
+        opt(terminated(generic_suffix, multispace0)).parse(s)?
+    } else {
+        (s, None)
+    };
+    let generic = generic.map(|generic| Cow::Borrowed(&generic[1..generic.len() - 1]));
+
+    let (s, label) = opt(class_label).parse(s)?;
+    let label = label.map(Cow::Borrowed);
+
+    // `class Name:::important` styles the class with a CSS class
+    let (s, css_class) = opt(terminated(css_class_suffix, multispace0)).parse(s)?;
+    let css_class = css_class.map(Cow::Borrowed);
+
+    Ok((s, (name, generic, label, css_class)))
+}
+
+/// Parse a balanced `~...~` generic suffix. Mermaid uses the same character to open and close,
+/// so a `~` opens a nested generic when it directly follows a name and is followed by one,
+/// otherwise it closes the innermost generic. The suffix must close before the end of the line.
+fn generic_suffix(s: &str) -> IResult<&str, &str> {
+    let (rest, _) = char('~').parse(s)?;
+
+    let mut depth = 1;
+    let mut prev = '~';
+    let mut chars = rest.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\n' | '\r' => break,
+            '~' => {
+                let next = chars.peek().map(|&(_, c)| c);
+                if is_name_char(prev) && next.is_some_and(is_name_char) {
+                    depth += 1;
+                } else {
+                    depth -= 1;
+                    if depth == 0 {
+                        let end = 1 + i + c.len_utf8();
+                        return Ok((&s[end..], &s[..end]));
+                    }
+                }
+            }
+            _ => {}
+        }
+        prev = c;
+    }
+
+    Err(nom::Err::Error(MermaidParseError::Nom(
+        nom::error::ErrorKind::Char,
+    )))
 }
 
 // Originally this is:
@@ -420,23 +747,48 @@ pub fn class_method_param<'source>(
 //     | alphaNumToken GENERICTYPE { $$=$1+'~'+$2+'~'; }
 //     | classLiteralName GENERICTYPE { $$=$1+'~'+$2+'~'; }
 //     ;
-// We don't care about generic though.
+// The generic is picked up by `class_stmt` rather than here, so relations and member lines still
+// name the class without it.
 // NOTE: alphaNumToken  : UNICODE_TEXT | NUM | ALPHA | MINUS;
+// UNICODE_TEXT is covered by `is_name_char`.
 pub fn class_name(s: &str) -> IResult<&str, &str> {
-    use nom::{bytes::complete::take_while, combinator::recognize, sequence::pair};
+    use nom::{combinator::recognize, multi::many0, sequence::pair};
+
+    // A dash only continues a name when a name character follows it, so `A-->B` stops at `A`
+    // while `my-class` stays whole.
+    let alpha_num_token = || {
+        let word = || take_while1(is_name_char);
+        recognize(pair(word(), many0(pair(char('-'), word()))))
+    };
 
     // Skip leading whitespace
     let (s, _) = multispace0.parse(s)?;
 
+    // A backtick with no partner on the same line is a hard error rather than "not a name". We
+    // don't know where `s` sits in the source here, so the offset holds the length of the input
+    // left from the backtick and `parse_mermaid` turns it into a position.
+    if let Some(rest) = s.strip_prefix('`') {
+        let line = rest.find(['\r', '\n']).map_or(rest, |end| &rest[..end]);
+        if !line.contains('`') {
+            return Err(nom::Err::Failure(MermaidParseError::UnterminatedBacktick {
+                offset: s.len(),
+                line: 0,
+                text: String::new(),
+            }));
+        }
+    }
+
     // Parse either backtick-escaped name or regular name
     let (s, name) = alt((
         // Backtick-escaped name (for special characters)
         delimited(char('`'), take_while1(|c: char| c != '`'), char('`')),
         // Regular alphanumeric name: must start with alphanumeric or underscore,
-        // can continue with alphanumeric, underscore, or dash
+        // can continue with alphanumeric, underscore, or inner dashes. Dots join tokens
+        // (`com.example.Foo`) but only when another token follows, so `A..>B` stops at `A`.
+        // `::` joins a namespace to the class it holds (`Animals::Dog`) the same way.
         recognize(pair(
-            take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-            take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+            alpha_num_token(),
+            many0(pair(alt((tag("."), tag("::"))), alpha_num_token())),
         )),
     ))
     .parse(s)?;
@@ -463,6 +815,236 @@ mod tests {
         let (rem, name) = class_name("\t \t Whitespace  ").expect("Failed to parse whitespace");
         assert!(rem.is_empty());
         assert_eq!(name, "Whitespace");
+
+        let (rem, name) = class_name("_private.pkg.Foo").expect("Failed to parse dotted name");
+        assert!(rem.is_empty());
+        assert_eq!(name, "_private.pkg.Foo");
+
+        // A dot that doesn't join two tokens belongs to whatever comes next
+        let (rem, name) = class_name("A..>B").expect("Failed to parse name before operator");
+        assert_eq!(rem, "..>B");
+        assert_eq!(name, "A");
+
+        let (rem, name) = class_name("a.b.C..B").expect("Failed to parse dotted name");
+        assert_eq!(rem, "..B");
+        assert_eq!(name, "a.b.C");
+
+        let (rem, name) = class_name("Animals::Dog --> B").expect("Failed to parse qualified name");
+        assert_eq!(rem, "--> B");
+        assert_eq!(name, "Animals::Dog");
+
+        // Like a dot, `::` only joins two tokens
+        let (rem, name) = class_name("Dog :: x").expect("Failed to parse name before colons");
+        assert_eq!(rem, ":: x");
+        assert_eq!(name, "Dog");
+
+        // Likewise a dash that starts an arrow isn't part of the name
+        let (rem, name) = class_name("my-class-->B").expect("Failed to parse name before arrow");
+        assert_eq!(rem, "-->B");
+        assert_eq!(name, "my-class");
+    }
+
+    #[test]
+    fn test_class_stmt_list() {
+        let (rem, Stmt::Classes(classes)) =
+            class_stmt("class A, B, C\nA --> B").expect("Failed to parse")
+        else {
+            panic!("Expected a class list");
+        };
+        assert_eq!(rem, "A --> B");
+        let names: Vec<_> = classes.iter().map(|class| class.name.as_ref()).collect();
+        assert_eq!(names, ["A", "B", "C"]);
+        assert!(classes.iter().all(|class| class.members.is_empty()));
+
+        // Any name in the list can carry a generic, label or CSS class, and has its own span
+        let source = "class A, B~T~[\"Bee\"], C:::hot\nA --> B";
+        let options = ParseOptions {
+            spans: true,
+            ..Default::default()
+        };
+        let (rem, Stmt::Classes(classes)) =
+            class_stmt_with(source, &options).expect("Failed to parse")
+        else {
+            panic!("Expected a class list");
+        };
+        assert_eq!(rem, "A --> B");
+        assert_eq!(classes[0].generic, None);
+        assert_eq!(classes[1].generic, Some("T".into()));
+        assert_eq!(classes[1].label, Some("Bee".into()));
+        assert_eq!(classes[2].css_class, Some("hot".into()));
+        let spans: Vec<_> = classes
+            .iter()
+            .map(|class| class.span.map(|span| &source[span.start..span.end]))
+            .collect();
+        assert_eq!(
+            spans,
+            [Some("class A"), Some("B~T~[\"Bee\"]"), Some("C:::hot")]
+        );
+    }
+
+    #[test]
+    fn test_class_stmt_dotted_name() {
+        let (rem, Stmt::Class(class)) = class_stmt("class a.b.C").expect("Failed to parse") else {
+            panic!("Returned a non class statement");
+        };
+        assert!(rem.is_empty());
+        assert_eq!(class.name, "a.b.C");
+    }
+
+    #[test]
+    fn test_class_member_list() {
+        let (rem, members) =
+            class_member_list("+bark() void, +age: int\n").expect("Failed to parse members");
+        assert_eq!(rem, "\n");
+        assert_eq!(members.len(), 2);
+        assert!(matches!(&members[0], Member::Method(m) if m.name == "bark"));
+        assert!(matches!(&members[1], Member::Attribute(a) if a.name == "age"));
+
+        // Commas in a parameter list don't split the member
+        let (rem, members) =
+            class_member_list("+feed(food: Food, n: int)").expect("Failed to parse members");
+        assert!(rem.is_empty());
+        assert_eq!(members.len(), 1);
+    }
+
+    #[test]
+    fn test_class_inline_annotation() {
+        let (rem, Stmt::Class(class)) =
+            class_stmt("class Shape <<interface>> { +area() float }").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(class.name, "Shape");
+        assert_eq!(class.annotations, [AnnotationKind::Interface]);
+        assert_eq!(class.members.len(), 1);
+        assert_eq!(class.members[0].name(), "area");
+
+        let (rem, Stmt::Class(class)) =
+            class_stmt("class Color <<enumeration>>\nclass Next").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(rem, "class Next");
+        assert_eq!(class.annotations, [AnnotationKind::Enumeration]);
+
+        // An annotation on the next line isn't part of the declaration
+        let (rem, Stmt::Class(class)) =
+            class_stmt("class Shape\n<<interface>> Shape").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(rem, "<<interface>> Shape");
+        assert!(class.annotations.is_empty());
+    }
+
+    #[test]
+    fn test_class_generic() {
+        let (rem, Stmt::Class(class)) =
+            class_stmt("class Square~Shape~[\"Box\"] {\n  +side: int\n}\nclass b")
+                .expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(rem, "class b");
+        assert_eq!(class.name, "Square");
+        assert_eq!(class.generic, Some("Shape".into()));
+        assert_eq!(class.label, Some("Box".into()));
+        assert_eq!(class.members.len(), 1);
+
+        let (_, Stmt::Class(class)) =
+            class_stmt("class Cache~K, List~V~~").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(class.generic, Some("K, List~V~".into()));
+
+        // An escaped name takes a generic too
+        let (_, Stmt::Class(class)) =
+            class_stmt("class `Bank Account`~T~").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(class.name, "Bank Account");
+        assert_eq!(class.generic, Some("T".into()));
+
+        let (_, Stmt::Class(class)) = class_stmt("class a").expect("Failed to parse") else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(class.generic, None);
+    }
+
+    #[test]
+    fn test_class_css_class() {
+        let (rem, Stmt::Class(class)) =
+            class_stmt("class Account:::important {\n  +balance: int\n}\nclass b")
+                .expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(rem, "class b");
+        assert_eq!(class.name, "Account");
+        assert_eq!(class.css_class, Some("important".into()));
+        assert_eq!(class.members.len(), 1);
+
+        let (_, Stmt::Class(class)) =
+            class_stmt("class Box~T~[\"Crate\"]:::dashed-border").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(class.generic, Some("T".into()));
+        assert_eq!(class.label, Some("Crate".into()));
+        assert_eq!(class.css_class, Some("dashed-border".into()));
+    }
+
+    #[test]
+    fn test_class_label() {
+        let (rem, Stmt::Class(class)) =
+            class_stmt("class a[\"A Label\"]\nclass b").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(rem, "class b");
+        assert_eq!(class.name, "a");
+        assert_eq!(class.label, Some("A Label".into()));
+
+        let (rem, Stmt::Class(class)) =
+            class_stmt("class `Big Cat`[\"Cat\"] {\n  +name: String\n}").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert!(rem.is_empty());
+        assert_eq!(class.name, "Big Cat");
+        assert_eq!(class.label, Some("Cat".into()));
+        assert_eq!(class.members.len(), 1);
+
+        let (_, Stmt::Class(class)) = class_stmt("class a").expect("Failed to parse") else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert_eq!(class.label, None);
+        assert!(class_label("[\"unterminated]").is_err());
+    }
+
+    #[test]
+    fn test_class_annotation() {
+        let (rem, annotation) = class_annotation("<< service >>\n").expect("Failed to parse");
+        assert_eq!(rem, "\n");
+        assert_eq!(annotation, "service");
+
+        assert!(class_annotation("<<unterminated\n>>").is_err());
+
+        let (rem, found) = annotation_stmt("<<interface>> Shape").expect("Failed to parse");
+        assert_eq!(rem, "");
+        assert_eq!(found, ("Shape", "interface"));
+        assert!(annotation_stmt("<<interface>>\nShape").is_err());
+
+        let (_, Stmt::Class(class)) =
+            class_stmt("class Shape {\n  <<interface>>\n  +draw()\n}").expect("Failed to parse")
+        else {
+            panic!("Returned a non class statement");
+        };
+        assert_eq!(class.annotations, [AnnotationKind::Interface]);
+        assert_eq!(class.members.len(), 1);
     }
 
     #[test]
@@ -528,6 +1110,183 @@ mod tests {
         assert_eq!(param.type_notation, TypeNotation::Postfix);
     }
 
+    #[test]
+    fn test_type_token() {
+        let (rem, ty) = type_token("int").expect("Failed to parse plain type");
+        assert!(rem.is_empty());
+        assert_eq!(ty, "int");
+
+        let (rem, ty) = type_token("List~Order~, count").expect("Failed to parse generic type");
+        assert_eq!(rem, ", count");
+        assert_eq!(ty, "List~Order~");
+
+        // An unterminated generic leaves the tilde behind
+        let (rem, ty) = type_token("List~Order").expect("Failed to parse unterminated generic");
+        assert_eq!(rem, "~Order");
+        assert_eq!(ty, "List");
+    }
+
+    #[test]
+    fn test_postfix_type() {
+        let (rem, ty) = postfix_type("Map < K , V >\n").expect("Failed to parse spaced generic");
+        assert_eq!(rem, "\n");
+        assert_eq!(ty, "Map<K, V>");
+
+        let (_, ty) = postfix_type("Map<String, List<Int>>").expect("Failed to parse generic");
+        assert!(matches!(ty, Cow::Borrowed("Map<String, List<Int>>")));
+
+        let (_, ty) =
+            postfix_type("Map<unsigned  long,V>").expect("Failed to parse multi word argument");
+        assert_eq!(ty, "Map<unsigned long, V>");
+
+        // Unbalanced brackets aren't part of the type
+        let (rem, ty) = postfix_type("List <Order").expect("Failed to parse unbalanced generic");
+        assert_eq!(rem, " <Order");
+        assert_eq!(ty, "List");
+
+        let (rem, attr) =
+            class_attribute("+items : Map < K , V >").expect("Failed to parse attribute");
+        assert!(rem.is_empty());
+        assert_eq!(attr.name, "items");
+        assert_eq!(attr.data_type, Some("Map<K, V>".into()));
+        assert_eq!(attr.type_notation, TypeNotation::Postfix);
+
+        let (rem, method) =
+            class_method("put(entry: Pair< K, V >, n: int)").expect("Failed to parse method");
+        assert!(rem.is_empty());
+        assert_eq!(method.parameters.len(), 2);
+        assert_eq!(method.parameters[0].data_type, Some("Pair<K, V>".into()));
+    }
+
+    #[test]
+    fn test_class_member_generic_types() {
+        let (rem, attr) = class_attribute("+items: List~int~").expect("Failed to parse attribute");
+        assert!(rem.is_empty());
+        assert_eq!(attr.name, "items");
+        assert_eq!(attr.data_type, Some("List~int~".into()));
+        assert_eq!(attr.type_notation, TypeNotation::Postfix);
+
+        let (rem, attr) = class_attribute("-Map~String, List~int~~ lookup")
+            .expect("Failed to parse nested generic prefix attribute");
+        assert!(rem.is_empty());
+        assert_eq!(attr.name, "lookup");
+        assert_eq!(attr.data_type, Some("Map~String, List~int~~".into()));
+        assert_eq!(attr.type_notation, TypeNotation::Prefix);
+
+        let (rem, method) = class_method("+lookup() Map~String, List~int~~")
+            .expect("Failed to parse nested generic return type");
+        assert!(rem.is_empty());
+        assert_eq!(method.return_type, Some("Map~String, List~int~~".into()));
+        assert_eq!(method.return_type_notation, TypeNotation::Postfix);
+
+        let (rem, method) =
+            class_method("List~int~ items()").expect("Failed to parse generic prefix return");
+        assert!(rem.is_empty());
+        assert_eq!(method.name, "items");
+        assert_eq!(method.return_type, Some("List~int~".into()));
+        assert_eq!(method.return_type_notation, TypeNotation::Prefix);
+    }
+
+    #[test]
+    fn test_class_method_line_continuation() {
+        let source = "+put(K key, \\\n        V value) void\n+get() V";
+        let options = ParseOptions {
+            allow_line_continuation: true,
+            ..Default::default()
+        };
+        let (rem, method) = class_method_with(source, &options).expect("Failed to parse method");
+        assert_eq!(rem, "\n+get() V");
+        assert_eq!(method.name, "put");
+        assert_eq!(method.parameters.len(), 2);
+        assert_eq!(method.parameters[1].name, "value");
+        assert_eq!(method.return_type, Some("void".into()));
+
+        let (_, method) = class_method_with("run( \\\n  a: int \\\n ) bool", &options)
+            .expect("Failed to parse method");
+        assert_eq!(method.parameters.len(), 1);
+        assert_eq!(method.return_type, Some("bool".into()));
+
+        // Off by default
+        assert!(class_method(source).is_err());
+    }
+
+    #[test]
+    fn test_class_method_classifier_order() {
+        for source in ["+*$ foo()", "+$* foo()", "+$ * foo()", "+* $foo()"] {
+            let (rem, method) = class_method(source).expect(source);
+            assert!(rem.is_empty(), "{source}");
+            assert_eq!(method.visibility, Visibility::Public, "{source}");
+            assert_eq!(method.name, "foo", "{source}");
+            assert!(method.is_static && method.is_abstract, "{source}");
+        }
+
+        let (_, method) = class_method("#*bar() int").expect("Failed to parse method");
+        assert!(method.is_abstract && !method.is_static);
+    }
+
+    #[test]
+    fn test_class_method_empty_and_trailing_comma() {
+        for source in ["reset() bool", "reset( ) bool", "reset(,) bool"] {
+            let (rem, method) = class_method(source).expect(source);
+            assert!(rem.is_empty(), "{source}");
+            assert!(method.parameters.is_empty(), "{source}");
+            assert_eq!(method.return_type, Some("bool".into()), "{source}");
+        }
+
+        let (rem, method) = class_method("put(a, ) void").expect("Failed to parse method");
+        assert!(rem.is_empty());
+        assert_eq!(method.parameters.len(), 1);
+        assert_eq!(method.parameters[0].name, "a");
+        assert_eq!(method.return_type, Some("void".into()));
+
+        let (_, method) = class_method("put(key: K, value: V,)").expect("Failed to parse method");
+        assert_eq!(method.parameters.len(), 2);
+        assert_eq!(method.parameters[1].name, "value");
+    }
+
+    #[test]
+    fn test_class_method_param_default() {
+        let (rem, method) = class_method("method(x: int = 5)").expect("Failed to parse method");
+        assert!(rem.is_empty());
+        assert_eq!(method.parameters[0].name, "x");
+        assert_eq!(method.parameters[0].data_type, Some("int".into()));
+        assert_eq!(method.parameters[0].default_value, Some("5".into()));
+
+        let (_, method) = class_method("method(name: String = \"a, b\", int n=-1, flag = true)")
+            .expect("Failed to parse method");
+        let defaults: Vec<_> = method
+            .parameters
+            .iter()
+            .map(|param| param.default_value.as_deref())
+            .collect();
+        assert_eq!(defaults, [Some("\"a, b\""), Some("-1"), Some("true")]);
+        assert_eq!(method.parameters[1].type_notation, TypeNotation::Prefix);
+        assert_eq!(method.parameters[2].type_notation, TypeNotation::None);
+
+        let (_, param) = class_method_param("x: int").expect("Failed to parse param");
+        assert_eq!(param.default_value, None);
+    }
+
+    #[test]
+    fn test_class_method_generic_param() {
+        let (rem, method) = class_method("foo(items: List~Order~, count: int)")
+            .expect("Failed to parse method with generic parameter");
+        assert!(rem.is_empty());
+        assert_eq!(method.parameters.len(), 2);
+        assert_eq!(method.parameters[0].name, "items");
+        assert_eq!(method.parameters[0].data_type, Some("List~Order~".into()));
+        assert_eq!(method.parameters[0].type_notation, TypeNotation::Postfix);
+        assert_eq!(method.parameters[1].name, "count");
+        assert_eq!(method.parameters[1].data_type, Some("int".into()));
+
+        let (rem, param) =
+            class_method_param("List~Order~ items").expect("Failed to parse prefix generic");
+        assert!(rem.is_empty());
+        assert_eq!(param.name, "items");
+        assert_eq!(param.data_type, Some("List~Order~".into()));
+        assert_eq!(param.type_notation, TypeNotation::Prefix);
+    }
+
     #[test]
     fn test_class_attribute() {
         // Test private attribute with prefix notation: - int age
@@ -537,7 +1296,7 @@ mod tests {
         assert_eq!(attr.visibility, Visibility::Private);
         assert_eq!(attr.name, "age");
         assert_eq!(attr.data_type, Some("int".into()));
-        assert_eq!(attr.is_static, false);
+        assert!(!attr.is_static);
         assert_eq!(attr.type_notation, TypeNotation::Prefix);
 
         // Test public attribute with postfix notation: + name: String
@@ -547,7 +1306,7 @@ mod tests {
         assert_eq!(attr.visibility, Visibility::Public);
         assert_eq!(attr.name, "name");
         assert_eq!(attr.data_type, Some("String".into()));
-        assert_eq!(attr.is_static, false);
+        assert!(!attr.is_static);
         assert_eq!(attr.type_notation, TypeNotation::Postfix);
 
         // Test static attribute: + $ count: int
@@ -557,7 +1316,7 @@ mod tests {
         assert_eq!(attr.visibility, Visibility::Public);
         assert_eq!(attr.name, "count");
         assert_eq!(attr.data_type, Some("int".into()));
-        assert_eq!(attr.is_static, true);
+        assert!(attr.is_static);
         assert_eq!(attr.type_notation, TypeNotation::Postfix);
 
         // Test attribute without type: # id
@@ -566,7 +1325,7 @@ mod tests {
         assert_eq!(attr.visibility, Visibility::Protected);
         assert_eq!(attr.name, "id");
         assert_eq!(attr.data_type, None);
-        assert_eq!(attr.is_static, false);
+        assert!(!attr.is_static);
         assert_eq!(attr.type_notation, TypeNotation::None);
 
         // Test attribute without visibility (unspecified)
@@ -591,8 +1350,8 @@ mod tests {
         assert_eq!(method.parameters[0].name, "distance");
         assert_eq!(method.parameters[0].data_type, Some("int".into()));
         assert_eq!(method.return_type, Some("void".into()));
-        assert_eq!(method.is_static, false);
-        assert_eq!(method.is_abstract, false);
+        assert!(!method.is_static);
+        assert!(!method.is_abstract);
         assert_eq!(method.return_type_notation, TypeNotation::Prefix);
 
         // Test private method with postfix return: - digest(Food food) void
@@ -605,8 +1364,8 @@ mod tests {
         assert_eq!(method.parameters[0].name, "food");
         assert_eq!(method.parameters[0].data_type, Some("Food".into()));
         assert_eq!(method.return_type, Some("void".into()));
-        assert_eq!(method.is_static, false);
-        assert_eq!(method.is_abstract, false);
+        assert!(!method.is_static);
+        assert!(!method.is_abstract);
         assert_eq!(method.return_type_notation, TypeNotation::Postfix);
 
         // Test method without visibility and multiple parameters
@@ -631,8 +1390,8 @@ mod tests {
         assert_eq!(method.name, "getInstance");
         assert_eq!(method.parameters.len(), 0);
         assert_eq!(method.return_type, Some("Singleton".into()));
-        assert_eq!(method.is_static, true);
-        assert_eq!(method.is_abstract, false);
+        assert!(method.is_static);
+        assert!(!method.is_abstract);
 
         // Test abstract method: + * calculate() void
         let (rem, method) =
@@ -640,8 +1399,8 @@ mod tests {
         assert!(rem.is_empty());
         assert_eq!(method.visibility, Visibility::Public);
         assert_eq!(method.name, "calculate");
-        assert_eq!(method.is_static, false);
-        assert_eq!(method.is_abstract, true);
+        assert!(!method.is_static);
+        assert!(method.is_abstract);
 
         // Test method without return type: # process(data)
         let (rem, method) =
@@ -704,6 +1463,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_class_stmt_attribute_and_method_same_name() {
+        let (rem, Stmt::Class(class)) =
+            class_stmt("class Counter {\n  count\n  count()\n  +count: int\n  +count() int\n}")
+                .expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Class");
+        };
+        assert!(rem.is_empty());
+        assert_eq!(class.members.len(), 4);
+
+        // A bare `count` stays an attribute even with `count()` on the next line
+        let Member::Attribute(bare) = &class.members[0] else {
+            panic!("count should be an attribute");
+        };
+        assert_eq!(bare.name, "count");
+        assert_eq!(bare.data_type, None);
+        let Member::Method(method) = &class.members[1] else {
+            panic!("count() should be a method");
+        };
+        assert_eq!(method.name, "count");
+        assert!(method.parameters.is_empty());
+
+        assert!(
+            matches!(&class.members[2], Member::Attribute(attr) if attr.data_type == Some("int".into()))
+        );
+        assert!(
+            matches!(&class.members[3], Member::Method(method) if method.return_type == Some("int".into()))
+        );
+    }
+
     #[test]
     fn test_class_stmt() {
         let class = "
@@ -740,6 +1530,7 @@ class Next";
             data_type: Some("int".into()),
             is_static: false,
             type_notation: TypeNotation::Prefix,
+            span: None,
         });
 
         let name = Member::Attribute(Attribute {
@@ -748,6 +1539,7 @@ class Next";
             data_type: Some("String".into()),
             is_static: false,
             type_notation: TypeNotation::Postfix,
+            span: None,
         });
 
         let swim = Member::Method(Method {
@@ -757,11 +1549,13 @@ class Next";
                 name: "distance".into(),
                 data_type: Some("int".into()),
                 type_notation: TypeNotation::Postfix,
+                default_value: None,
             }],
             return_type: Some("void".into()),
             is_static: false,
             is_abstract: false,
             return_type_notation: TypeNotation::Prefix,
+            span: None,
         });
 
         let digest = Member::Method(Method {
@@ -771,11 +1565,13 @@ class Next";
                 name: "food".into(),
                 data_type: Some("Food".into()),
                 type_notation: TypeNotation::Prefix,
+                default_value: None,
             }],
             return_type: Some("void".into()),
             is_static: false,
             is_abstract: false,
             return_type_notation: TypeNotation::Postfix,
+            span: None,
         });
 
         let sleep = Member::Method(Method {
@@ -786,20 +1582,23 @@ class Next";
                     name: "time".into(),
                     data_type: Some("Time".into()),
                     type_notation: TypeNotation::Postfix,
+                    default_value: None,
                 },
                 Parameter {
                     name: "hemisphere".into(),
                     data_type: Some("Hemisphere".into()),
                     type_notation: TypeNotation::Prefix,
+                    default_value: None,
                 },
             ],
             return_type: Some("Int".into()),
             is_static: false,
             is_abstract: false,
             return_type_notation: TypeNotation::Postfix,
+            span: None,
         });
 
-        let expected_members = vec![age, name, swim, digest, sleep];
+        let expected_members = [age, name, swim, digest, sleep];
 
         for (i, (expected, actual)) in expected_members
             .iter()
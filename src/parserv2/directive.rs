@@ -0,0 +1,44 @@
+use super::{IResult, MermaidParseError};
+use nom::{
+    Parser,
+    bytes::complete::{tag, take_until},
+    character::complete::{line_ending, space0},
+    combinator::opt,
+    sequence::delimited,
+};
+
+/// # Parse a config directive
+///
+/// Directives look like `%%{init: {'theme': 'forest'}}%%`. What sits between `%%{` and `}%%` is a
+/// mapping in relaxed YAML, which also covers the JSON Mermaid's own docs use. A directive that
+/// isn't valid YAML is not a directive, the caller treats the line as an ordinary comment.
+pub fn directive(s: &str) -> IResult<&str, serde_yml::Value> {
+    let (rem, inner) = delimited(tag("%%{"), take_until("}%%"), tag("}%%")).parse(s)?;
+    let (rem, _) = (space0, opt(line_ending)).parse(rem)?;
+
+    let value = serde_yml::from_str::<serde_yml::Value>(&format!("{{{inner}}}"))
+        .map_err(|err| nom::Err::Error(MermaidParseError::SerdeYml(err)))?;
+
+    Ok((rem, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directive() {
+        let (rem, value) =
+            directive("%%{init: {'theme':'forest'}}%%\nclassDiagram").expect("Failed to parse");
+        assert_eq!(rem, "classDiagram");
+        assert_eq!(value["init"]["theme"], "forest");
+
+        let (_, value) = directive(r#"%%{ init: { "theme": "dark", "fontSize": 12 } }%%"#)
+            .expect("Failed to parse JSON style directive");
+        assert_eq!(value["init"]["theme"], "dark");
+        assert_eq!(value["init"]["fontSize"], 12);
+
+        assert!(directive("%% plain comment").is_err());
+        assert!(directive("%%{init: {'theme'}%%").is_err());
+    }
+}
@@ -1,11 +1,5 @@
 use super::{IResult, MermaidParseError};
-use nom::{
-    Err, Parser,
-    bytes::complete::{tag, take_until},
-    character::complete::line_ending,
-    combinator::opt,
-    sequence::delimited,
-};
+use nom::{Err, error::ErrorKind};
 
 /// # Parse the Yaml frontmatter
 ///
@@ -16,22 +10,33 @@ use nom::{
 /// it has no frontmatter, if it has this and we fail to parse that is considered a failure to parse
 /// the frontmatter.
 pub fn frontmatter(s: &str) -> IResult<&str, Option<serde_yml::Value>> {
+    let (rem, frontmatter) = frontmatter_raw(s)?;
+    Ok((rem, frontmatter.map(|(_, yaml)| yaml)))
+}
+
+/// Like [`frontmatter`], but also return the text between the `---` lines exactly as written
+pub fn frontmatter_raw(s: &str) -> IResult<&str, Option<(&str, serde_yml::Value)>> {
     // Detection to distinguish between having no frontmatter and a failure to
     // parse it.
     if !s.starts_with("---") {
         return Ok((s, None));
     }
 
-    // We can skip consuming the first line ending since `serde_yml` can handle it.
-    // Still need to consume the last though.
-    let (rem, yaml) = delimited(
-        tag("---"),
-        take_until("---"),
-        (tag("---"), opt(line_ending)),
-    )
-    .parse(s)?;
+    // We can skip consuming the first line ending since `serde_yml` can handle it. The closing
+    // `---` has to be on a line of its own, so one inside the YAML (`title: "a---b"`) doesn't end
+    // it early, and the line ending before it may be `\r\n`.
+    let body = &s[3..];
+    let mut lines = body.split_inclusive('\n');
+    let mut offset = lines.next().map_or(0, str::len);
+    let Some((yaml, rem)) = lines.find_map(|line| {
+        let start = offset;
+        offset += line.len();
+        (line.trim_end() == "---").then(|| (&body[..start], &body[offset..]))
+    }) else {
+        return Err(Err::Error(MermaidParseError::Nom(ErrorKind::TakeUntil)));
+    };
 
-    Ok((rem, Some(frontmatter_context(yaml)?)))
+    Ok((rem, Some((yaml, frontmatter_context(yaml)?))))
 }
 
 /// Parse Yaml with `serde_yml`. BE AWARE: this function needs a complete
@@ -39,7 +44,41 @@ pub fn frontmatter(s: &str) -> IResult<&str, Option<serde_yml::Value>> {
 pub fn frontmatter_context(
     yaml: &str,
 ) -> Result<serde_yml::Value, nom::Err<super::MermaidParseError>> {
-    Ok(serde_yml::from_str::<serde_yml::Value>(yaml)
+    serde_yml::from_str::<serde_yml::Value>(yaml)
         .map_err(MermaidParseError::SerdeYml)
-        .map_err(Err::Failure)?)
+        .map_err(Err::Failure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frontmatter_crlf() {
+        let (rem, frontmatter) =
+            frontmatter_raw("---\r\ntitle: Animals\r\n---\r\nclassDiagram\r\n")
+                .expect("Failed to parse frontmatter");
+        assert_eq!(rem, "classDiagram\r\n");
+        let (raw, yaml) = frontmatter.unwrap();
+        assert_eq!(raw, "\r\ntitle: Animals\r\n");
+        assert_eq!(yaml["title"], "Animals");
+    }
+
+    #[test]
+    fn test_frontmatter_dashes_in_value() {
+        let source = "---\ntitle: \"before --- after\"\nnote: a---b\n---\nclassDiagram\n";
+        let (rem, yaml) = frontmatter(source).expect("Failed to parse frontmatter");
+        assert_eq!(rem, "classDiagram\n");
+        let yaml = yaml.unwrap();
+        assert_eq!(yaml["title"], "before --- after");
+        assert_eq!(yaml["note"], "a---b");
+    }
+
+    #[test]
+    fn test_frontmatter_unterminated() {
+        assert!(frontmatter("---\ntitle: Animals\nclassDiagram\n").is_err());
+        let (rem, yaml) = frontmatter("classDiagram\n").expect("No frontmatter isn't an error");
+        assert_eq!(rem, "classDiagram\n");
+        assert!(yaml.is_none());
+    }
 }
@@ -0,0 +1,119 @@
+//! Helpers for diagrams embedded in Markdown as ```` ```mermaid ```` fenced code blocks.
+
+use super::{MermaidParseError, parse_mermaid};
+use crate::types::Diagram;
+
+/// Return the contents of the first ```` ```mermaid ```` fenced block in `markdown`, without the
+/// fence lines. The result can be handed straight to `parse_mermaid`.
+pub fn extract_mermaid_block(markdown: &str) -> Option<&str> {
+    mermaid_blocks(markdown).next()
+}
+
+/// Parse every class diagram found in the ```` ```mermaid ```` blocks of `markdown`, in document
+/// order. Blocks holding other kinds of Mermaid diagram are skipped, while a class diagram that
+/// fails to parse is an error.
+pub fn parse_from_markdown(markdown: &str) -> Result<Vec<Diagram<'_>>, MermaidParseError> {
+    let mut diagrams = Vec::new();
+    for block in mermaid_blocks(markdown) {
+        match parse_mermaid(block) {
+            Ok(diagram) => diagrams.push(diagram),
+            Err(MermaidParseError::ExpectedClassDiagram) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(diagrams)
+}
+
+/// Iterate over the contents of every ```` ```mermaid ```` (or `~~~mermaid`) fenced block. A
+/// block that is never closed runs to the end of the document, like it does in Markdown.
+pub fn mermaid_blocks(markdown: &str) -> impl Iterator<Item = &str> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let mut open: Option<(&str, usize)> = None;
+
+        for line in markdown[offset..].split_inclusive('\n') {
+            let line_start = offset;
+            offset += line.len();
+            let trimmed = line.trim();
+
+            match open {
+                None => {
+                    let Some(fence) = fence(trimmed) else {
+                        continue;
+                    };
+                    let info = trimmed[fence.len()..].split_whitespace().next();
+                    if info == Some("mermaid") {
+                        open = Some((fence, offset));
+                    }
+                }
+                Some((fence_str, body_start)) => {
+                    // A closing fence uses the same character and is at least as long
+                    let fence_char = fence_str.chars().next()?;
+                    let closes = trimmed.chars().all(|c| c == fence_char);
+                    if closes && trimmed.len() >= fence_str.len() {
+                        return Some(&markdown[body_start..line_start]);
+                    }
+                }
+            }
+        }
+
+        open.map(|(_, body_start)| &markdown[body_start..])
+    })
+}
+
+/// The run of three or more backticks or tildes opening a fence, if `line` starts with one
+fn fence(line: &str) -> Option<&str> {
+    let c = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.len() - line.trim_start_matches(c).len();
+    (len >= 3).then(|| &line[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::serialize_diagram;
+
+    const MARKDOWN: &str = "# Design\n\nSome prose.\n\n```rust\nfn main() {}\n```\n\n```mermaid\nclassDiagram\nclass Animal\nAnimal --> Food\n```\n\nMore prose.\n";
+
+    #[test]
+    fn test_extract_mermaid_block() {
+        let block = extract_mermaid_block(MARKDOWN).expect("Failed to find the mermaid block");
+        assert_eq!(block, "classDiagram\nclass Animal\nAnimal --> Food\n");
+
+        let diagram = parse_mermaid(block).expect("Failed to parse the extracted block");
+        assert_eq!(diagram.relations.len(), 1);
+
+        // The serialized diagram can be fenced again and extracted to the same diagram
+        let fenced = format!("```mermaid\n{}```\n", serialize_diagram(&diagram));
+        let block = extract_mermaid_block(&fenced).expect("Failed to find re-fenced block");
+        assert_eq!(parse_mermaid(block).unwrap(), diagram);
+    }
+
+    #[test]
+    fn test_extract_mermaid_block_edge_cases() {
+        assert_eq!(extract_mermaid_block("no fences here"), None);
+        assert_eq!(extract_mermaid_block("```rust\nlet x = 1;\n```\n"), None);
+
+        // Tilde fences, CRLF line endings and an unterminated fence
+        assert_eq!(
+            extract_mermaid_block("~~~ mermaid\r\nclassDiagram\r\n~~~\r\n"),
+            Some("classDiagram\r\n")
+        );
+        assert_eq!(
+            extract_mermaid_block("```mermaid\nclassDiagram\nclass A"),
+            Some("classDiagram\nclass A")
+        );
+    }
+
+    #[test]
+    fn test_parse_from_markdown() {
+        let markdown = "```mermaid\nclassDiagram\nclass A\n```\n\n```mermaid\nflowchart LR\n  a --> b\n```\n\n```mermaid\n%% second\nclassDiagram-v2\nA --> B\n```\n";
+        let diagrams = parse_from_markdown(markdown).expect("Failed to parse markdown");
+        assert_eq!(diagrams.len(), 2);
+        assert!(diagrams[0].relations.is_empty());
+        assert_eq!(diagrams[1].relations.len(), 1);
+
+        assert!(parse_from_markdown("```mermaid\nclassDiagram\n!!!\n```\n").is_err());
+        assert!(parse_from_markdown("# Nothing to see").unwrap().is_empty());
+    }
+}
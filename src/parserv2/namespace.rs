@@ -5,15 +5,34 @@ use nom::{
     branch::alt,
     bytes::complete::{tag, take_while, take_while1},
     character::complete::{char, multispace0, space0, space1},
-    combinator::{opt, recognize},
+    combinator::{map, recognize},
     sequence::{delimited, pair, preceded},
     Parser,
 };
 
-use super::{class, IResult, MermaidParseError, Stmt};
-use crate::types::{Class, Direction, Member, Namespace, Note};
+use super::{class, IResult, MermaidParseError, ParseOptions, Stmt};
+use crate::types::{Class, Direction, Namespace, Note, NotePlacement};
 
 pub fn namespace_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'source>> {
+    namespace_stmt_with(s, &ParseOptions::default())
+}
+
+/// Like [`namespace_stmt`], but classes in the block are parsed with [`class::class_stmt_strict`]
+pub fn namespace_stmt_strict<'source>(s: &'source str) -> IResult<&'source str, Stmt<'source>> {
+    let options = ParseOptions {
+        strict: true,
+        ..Default::default()
+    };
+    namespace_stmt_with(s, &options)
+}
+
+/// [`namespace_stmt`] using the given options for the classes in the block. Spans are relative
+/// to the start of `s`.
+pub fn namespace_stmt_with<'source>(
+    s: &'source str,
+    options: &ParseOptions,
+) -> IResult<&'source str, Stmt<'source>> {
+    let input = s;
     let (s, _) = multispace0.parse(s)?;
 
     // Parse "namespace Name"
@@ -26,6 +45,9 @@ pub fn namespace_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'s
 
     // Parse class declarations and member statements within the namespace
     let mut classes: HashMap<Cow<'source, str>, Class<'source>> = HashMap::new();
+    let mut next_order = 0;
+    let mut children = HashMap::new();
+    let mut direction = None;
     let mut s = s;
 
     loop {
@@ -41,21 +63,57 @@ pub fn namespace_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'s
         }
 
         // Check for comment line (starts with %%)
-        if let Ok((s_new, _)) = tag::<_, _, nom::error::Error<_>>("%%").parse(s) {
-            // Skip the rest of the line
-            let (s_new, _) = take_while(|c| c != '\n' && c != '\r').parse(s_new)?;
+        if let Ok((s_new, _)) = super::comment(s) {
+            s = s_new;
+            continue;
+        }
+
+        if let Ok((s_new, found)) = stmt_direction(s) {
+            direction = Some(found);
             s = s_new;
             continue;
         }
 
-        // Try to parse full class statement (including brace notation)
-        if let Ok((s_new, stmt)) = class::class_stmt(s) {
-            if let Stmt::Class(class) = stmt {
-                // Insert or merge the class
-                classes.insert(class.name.clone(), class);
+        // A nested namespace block, whose classes are numbered along with ours
+        match namespace_stmt_with(s, options) {
+            Ok((s_new, Stmt::Namespace(mut child))) => {
+                let mut shift = super::ShiftSpans(input.len() - s.len());
+                let mut nested = child.all_classes_mut();
+                nested.sort_by_key(|class| class.order);
+                for class in nested {
+                    shift.class(class);
+                    class.order = next_order;
+                    next_order += 1;
+                }
+                children.insert(child.name.clone(), child);
+                s = s_new;
+                continue;
+            }
+            Err(err @ nom::Err::Failure(_)) => return Err(err),
+            _ => {}
+        }
+
+        // Try to parse full class statement (including brace notation), remembering the order
+        // within this block
+        let class_stmt = class::class_stmt_with(s, options);
+        let mut shift = super::ShiftSpans(input.len() - s.len());
+        match class_stmt {
+            Ok((s_new, Stmt::Class(mut class))) => {
+                shift.class(&mut class);
+                super::declare_class(&mut classes, class, &mut next_order);
                 s = s_new;
                 continue;
             }
+            Ok((s_new, Stmt::Classes(declared))) => {
+                for mut class in declared {
+                    shift.class(&mut class);
+                    super::declare_class(&mut classes, class, &mut next_order);
+                }
+                s = s_new;
+                continue;
+            }
+            Err(err @ nom::Err::Failure(_)) => return Err(err),
+            _ => {}
         }
 
         // Try to parse "ClassName : member" statement
@@ -64,25 +122,39 @@ pub fn namespace_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'s
             if let Ok((s_new2, _)) = char::<_, MermaidParseError>(':').parse(s_new) {
                 // Parse the member
                 let (s_new3, _) = space0.parse(s_new2)?;
-                if let Ok((s_new4, member)) = class::class_member_stmt(s_new3) {
-                    // Add member to the class
-                    if let Some(class) = classes.get_mut(&Cow::Borrowed(class_name)) {
-                        class.members.push(member);
+                if let Ok((s_new4, annotation)) = class::class_annotation(s_new3) {
+                    class_or_declare(&mut classes, class_name, &mut next_order)
+                        .annotate(annotation.into());
+                    s = s_new4;
+                    continue;
+                }
+                if let Ok((s_new4, mut members)) = class::class_member_list_with(s_new3, options) {
+                    if options.spans {
+                        for member in &mut members {
+                            let span = super::span_in(input, s, s_new4);
+                            *super::member_span_mut(member) = Some(span);
+                        }
                     }
+                    // Add members to the class
+                    class_or_declare(&mut classes, class_name, &mut next_order)
+                        .members
+                        .extend(members);
                     s = s_new4;
                     continue;
                 }
             }
         }
 
-        // If we can't parse anything, skip to the next line
-        if let Ok((s_new, _)) =
-            take_while::<_, _, nom::error::Error<_>>(|c| c != '\n' && c != '\r').parse(s)
-        {
+        // Try to parse "<<annotation>> ClassName" statement
+        if let Ok((s_new, (class_name, annotation))) = class::annotation_stmt(s) {
+            class_or_declare(&mut classes, class_name, &mut next_order).annotate(annotation.into());
             s = s_new;
-        } else {
-            break;
+            continue;
         }
+
+        // If we can't parse anything, skip to the next line
+        let (s_new, _) = super::skip_line(s)?;
+        s = s_new;
     }
 
     Ok((
@@ -90,22 +162,53 @@ pub fn namespace_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'s
         Stmt::Namespace(Namespace {
             name: Cow::Borrowed(name),
             classes,
-            children: HashMap::new(),
+            children,
+            direction,
         }),
     ))
 }
 
-pub fn namespace_identifier<'source>(s: &'source str) -> IResult<&'source str, &'source str> {
+/// The class `name` in the block, declaring it if this is the first we hear of it, as Mermaid
+/// does for members and annotations of a class that hasn't been declared yet
+fn class_or_declare<'a, 'source>(
+    classes: &'a mut HashMap<Cow<'source, str>, Class<'source>>,
+    name: &'source str,
+    next_order: &mut usize,
+) -> &'a mut Class<'source> {
+    if !classes.contains_key(name) {
+        let class = Class {
+            name: Cow::Borrowed(name),
+            generic: None,
+            label: None,
+            css_class: None,
+            annotations: Vec::new(),
+            members: Vec::new(),
+            order: 0,
+            span: None,
+        };
+        super::declare_class(classes, class, next_order);
+    }
+    classes.get_mut(name).expect("The class was just declared")
+}
+
+pub fn namespace_identifier(s: &str) -> IResult<&str, &str> {
     preceded((multispace0, tag("namespace"), space1), namespace_name).parse(s)
 }
 
-pub fn namespace_name<'source>(s: &'source str) -> IResult<&'source str, &'source str> {
+pub fn namespace_name(s: &str) -> IResult<&str, &str> {
     let (s, _) = multispace0.parse(s)?;
 
-    // Parse identifier: alphanumeric, underscore, dash
-    let (s, name) = recognize(pair(
-        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
-        take_while(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+    // Parse identifier: alphanumeric, underscore, dash, or anything but a line break in backticks
+    let (s, name) = alt((
+        delimited(
+            char('`'),
+            take_while1(|c: char| c != '`' && c != '\r' && c != '\n'),
+            char('`'),
+        ),
+        recognize(pair(
+            take_while1(class::is_name_char),
+            take_while(|c: char| class::is_name_char(c) || c == '-'),
+        )),
     ))
     .parse(s)?;
 
@@ -130,47 +233,158 @@ pub fn stmt_note<'source>(s: &'source str) -> IResult<&'source str, Note<'source
             let (s, _) = space0.parse(s)?;
 
             // Parse the note text in quotes
-            let (s, text) = delimited(char('"'), take_while(|c| c != '"'), char('"')).parse(s)?;
+            let (s, text) = note_text(s)?;
+
+            return Ok((
+                s,
+                Note {
+                    text,
+                    target_class: Some(Cow::Borrowed(class_name)),
+                    placement: None,
+                    span: None,
+                },
+            ));
+        }
+
+        // "note left of ClassName "text"" and the like, carried over from other diagram types
+        if let Ok((s, placement)) = note_placement(s) {
+            let (s, _) = space1.parse(s)?;
+            let (s, class_name) = class::class_name(s)?;
+            let (s, _) = space0.parse(s)?;
+            let (s, text) = note_text(s)?;
 
             return Ok((
                 s,
                 Note {
-                    text: Cow::Borrowed(text),
+                    text,
                     target_class: Some(Cow::Borrowed(class_name)),
+                    placement: Some(placement),
+                    span: None,
                 },
             ));
         }
 
         // Otherwise it's a general note: "note "text""
-        let (s, text) = delimited(char('"'), take_while(|c| c != '"'), char('"')).parse(s)?;
+        let (s, text) = note_text(s)?;
 
         return Ok((
             s,
             Note {
-                text: Cow::Borrowed(text),
+                text,
                 target_class: None,
+                placement: None,
+                span: None,
             },
         ));
     }
 
-    Err(nom::Err::Error(MermaidParseError::ExpectedStmt))
+    Err(nom::Err::Error(MermaidParseError::Nom(
+        nom::error::ErrorKind::Tag,
+    )))
+}
+
+fn note_placement(s: &str) -> IResult<&str, NotePlacement> {
+    alt((
+        map((tag("left"), space1, tag("of")), |_| NotePlacement::LeftOf),
+        map((tag("right"), space1, tag("of")), |_| {
+            NotePlacement::RightOf
+        }),
+        map(tag("over"), |_| NotePlacement::Over),
+    ))
+    .parse(s)
 }
 
+/// `direction TB` and friends. The direction is matched in any case (`direction lr`), and `TD` is
+/// another name for `TB`.
 pub fn stmt_direction(s: &str) -> IResult<&str, Direction> {
+    use nom::bytes::complete::tag_no_case;
+
     let (s, _) = multispace0.parse(s)?;
     let (s, _) = tag("direction").parse(s)?;
     let (s, _) = space1.parse(s)?;
 
-    let (s, dir_str) = alt((tag("TB"), tag("TD"), tag("BT"), tag("LR"), tag("RL"))).parse(s)?;
+    let (s, direction) = alt((
+        map(alt((tag_no_case("TB"), tag_no_case("TD"))), |_| {
+            Direction::TopBottom
+        }),
+        map(tag_no_case("BT"), |_| Direction::BottomTop),
+        map(tag_no_case("LR"), |_| Direction::LeftRight),
+        map(tag_no_case("RL"), |_| Direction::RightLeft),
+    ))
+    .parse(s)?;
 
-    let direction = match dir_str {
-        "TB" | "TD" => Direction::TopBottom,
-        "BT" => Direction::BottomTop,
-        "LR" => Direction::LeftRight,
-        "RL" => Direction::RightLeft,
-        _ => unreachable!(),
-    };
+    let (s, _) = multispace0.parse(s)?;
+
+    Ok((s, direction))
+}
+
+/// The quoted body of a note. A backslash escapes a quote or another backslash (`\"`, `\\`) and
+/// is kept as written before anything else, so `C:\dir` needs no escaping. The text is only
+/// copied when it contains an escape.
+fn note_text(s: &str) -> IResult<&str, Cow<'_, str>> {
+    let (body, _) = char('"').parse(s)?;
+
+    let mut has_escapes = false;
+    let mut chars = body.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if chars
+                .next_if(|&(_, next)| next == '"' || next == '\\')
+                .is_some() =>
+            {
+                has_escapes = true;
+            }
+            '"' => {
+                let text = &body[..i];
+                let text = if has_escapes {
+                    Cow::Owned(unescape_note_text(text))
+                } else {
+                    Cow::Borrowed(text)
+                };
+                return Ok((&body[i + 1..], text));
+            }
+            _ => {}
+        }
+    }
+
+    Err(nom::Err::Error(MermaidParseError::Nom(
+        nom::error::ErrorKind::Char,
+    )))
+}
+
+/// Undo the escapes [`note_text`] understands
+fn unescape_note_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(
+                chars
+                    .next_if(|&next| next == '"' || next == '\\')
+                    .unwrap_or(c),
+            ),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// [`stmt_direction`] that also accepts the words `up`, `down`, `left` and `right`
+pub fn stmt_direction_lenient(s: &str) -> IResult<&str, Direction> {
+    use nom::{bytes::complete::tag_no_case, combinator::map};
 
+    if let Ok(result) = stmt_direction(s) {
+        return Ok(result);
+    }
+
+    let (s, _) = (multispace0, tag("direction"), space1).parse(s)?;
+    let (s, direction) = alt((
+        map(tag_no_case("up"), |_| Direction::BottomTop),
+        map(tag_no_case("down"), |_| Direction::TopBottom),
+        map(tag_no_case("left"), |_| Direction::RightLeft),
+        map(tag_no_case("right"), |_| Direction::LeftRight),
+    ))
+    .parse(s)?;
     let (s, _) = multispace0.parse(s)?;
 
     Ok((s, direction))
@@ -201,6 +415,12 @@ mod tests {
         let (rem, name) = namespace_name("  MyNamespace  ").expect("Failed to parse with whitespace");
         assert!(rem.trim().is_empty());
         assert_eq!(name, "MyNamespace");
+
+        // Test backtick-escaped name with spaces
+        let (rem, name) = namespace_name("`My NS` {").expect("Failed to parse backtick name");
+        assert_eq!(rem, "{");
+        assert_eq!(name, "My NS");
+        assert!(namespace_name("`My\nNS`").is_err());
     }
 
     #[test]
@@ -245,6 +465,60 @@ mod tests {
         assert!(ns.classes.contains_key("Cat"));
     }
 
+    #[test]
+    fn test_namespace_stmt_direction() {
+        for input in [
+            "namespace N { direction LR class A }",
+            "namespace N {\n  direction LR\n  class A\n}\n",
+        ] {
+            let (rem, Stmt::Namespace(ns)) = namespace_stmt(input).expect("Failed to parse") else {
+                panic!("We should only be returning Stmt::Namespace");
+            };
+            assert!(rem.is_empty(), "There should be nothing left");
+            assert_eq!(ns.direction, Some(Direction::LeftRight));
+            assert!(ns.classes.contains_key("A"));
+        }
+
+        let (_, Stmt::Namespace(ns)) = namespace_stmt("namespace N {\n  class A\n}").unwrap()
+        else {
+            panic!("We should only be returning Stmt::Namespace");
+        };
+        assert_eq!(ns.direction, None);
+    }
+
+    #[test]
+    fn test_namespace_stmt_nested() {
+        let (rem, Stmt::Namespace(ns)) =
+            namespace_stmt("namespace Outer {\nclass A\nnamespace Inner {\nclass B\n}\nclass C\n}")
+                .expect("Failed to parse nested namespace")
+        else {
+            panic!("Expected Namespace statement");
+        };
+        assert!(rem.is_empty());
+        assert_eq!(ns.classes.len(), 2);
+        let inner = &ns.children["Inner"];
+        assert_eq!(inner.name, "Inner");
+
+        // Classes are numbered across the blocks in the order they are written
+        assert_eq!(ns.classes["A"].order, 0);
+        assert_eq!(inner.classes["B"].order, 1);
+        assert_eq!(ns.classes["C"].order, 2);
+    }
+
+    #[test]
+    fn test_namespace_stmt_class_list() {
+        let (rem, Stmt::Namespace(ns)) =
+            namespace_stmt("namespace Shapes {\nclass Circle, Square\n}")
+                .expect("Failed to parse namespace with a class list")
+        else {
+            panic!("Expected Namespace statement");
+        };
+        assert!(rem.is_empty());
+        assert_eq!(ns.classes.len(), 2);
+        assert_eq!(ns.classes["Circle"].order, 0);
+        assert_eq!(ns.classes["Square"].order, 1);
+    }
+
     #[test]
     fn test_namespace_stmt_with_members() {
         let input = r#"namespace Vehicles {
@@ -274,6 +548,44 @@ mod tests {
         assert_eq!(bike.members.len(), 1);
     }
 
+    #[test]
+    fn test_namespace_stmt_members_declare_class() {
+        // Members for a class the block hasn't declared declare it, in the order they appear
+        let (_, Stmt::Namespace(ns)) =
+            namespace_stmt("namespace N {\n  Car : +speed: int\n  class Bike\n}").unwrap()
+        else {
+            panic!("Expected Namespace statement");
+        };
+        assert_eq!(ns.classes["Car"].members.len(), 1);
+        assert_eq!(ns.classes["Car"].order, 0);
+        assert_eq!(ns.classes["Bike"].order, 1);
+
+        let (_, Stmt::Namespace(strict)) =
+            namespace_stmt_strict("namespace N {\n  Car : +speed: int\n  class Bike\n}").unwrap()
+        else {
+            panic!("Expected Namespace statement");
+        };
+        assert_eq!(strict, ns);
+    }
+
+    #[test]
+    fn test_namespace_stmt_standalone_annotation() {
+        let (_, Stmt::Namespace(ns)) =
+            namespace_stmt("namespace Shapes {\n  class Shape\n  <<interface>> Shape\n}").unwrap()
+        else {
+            panic!("Expected Namespace statement");
+        };
+        assert!(ns.classes["Shape"].is_interface());
+
+        // The annotation declares the class if nothing did before
+        let (_, Stmt::Namespace(ns)) =
+            namespace_stmt("namespace Shapes {\n  <<interface>> Shape\n}").unwrap()
+        else {
+            panic!("Expected Namespace statement");
+        };
+        assert!(ns.classes["Shape"].is_interface());
+    }
+
     #[test]
     fn test_namespace_stmt_with_newline_after_brace() {
         let input = r#"namespace Test {
@@ -0,0 +1,229 @@
+//! Parse a diagram from a [`BufRead`] without reading the whole input into memory first.
+
+use std::io::BufRead;
+
+use super::{DiagramBuilder, MermaidParseError, ParseOptions, body_items, parse_head, stuck};
+use crate::types::Diagram;
+
+/// Parse a diagram from `reader` line by line. Only the statement being read is kept in memory,
+/// along with the diagram built so far, which owns all of its data.
+///
+/// Lines go through the same contexts [`parse_mermaid`](super::parse_mermaid) describes. The
+/// frontmatter, directives and comments are collected up to the `classDiagram` header and parsed
+/// together. After that every line is parsed as it comes in, except that a statement is collected
+/// until it is complete: a `class` or `namespace` block until its braces are balanced, quoted text
+/// until its closing quote, and a bare `class Name` until the next line shows whether its body
+/// follows.
+pub fn parse_reader<R: BufRead>(mut reader: R) -> Result<Diagram<'static>, MermaidParseError> {
+    let options = ParseOptions::default();
+    let mut line = String::new();
+
+    let mut head = String::new();
+    let mut in_frontmatter = false;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let first = head.is_empty();
+        head.push_str(&line);
+        if first && line.starts_with("---") {
+            in_frontmatter = true;
+        } else if in_frontmatter {
+            // Only a line of its own closes the frontmatter, like in `frontmatter_raw`
+            in_frontmatter = line.trim_end() != "---";
+        } else {
+            let trimmed = line.trim();
+            // Anything but the header, comments and directives means there's no header coming
+            if trimmed.starts_with("classDiagram")
+                || !(trimmed.is_empty() || trimmed.starts_with("%%"))
+            {
+                break;
+            }
+        }
+    }
+
+    let (rest, diagram) = parse_head(&head)?;
+    let mut builder = DiagramBuilder::new(diagram.into_owned());
+
+    // Whatever followed the header on its line is the start of the body
+    let mut offset = head.len() - rest.len(); // bytes before `chunk`
+    let mut lines = head[..offset].matches('\n').count(); // lines before `chunk`
+    let mut chunk = rest.to_owned();
+    let mut pending = Pending::default();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read > 0 {
+            pending.push(&line);
+            chunk.push_str(&line);
+            if pending.is_pending() {
+                continue;
+            }
+        }
+
+        let rest = body_items(&chunk, &options, |item| builder.apply(item.into_owned()));
+        if !rest.is_empty() {
+            return Err(stuck(&chunk, rest, offset, lines, &options));
+        }
+        offset += chunk.len();
+        lines += chunk.matches('\n').count();
+        chunk.clear();
+
+        if read == 0 {
+            break;
+        }
+    }
+
+    Ok(builder.diagram)
+}
+
+/// Whether the lines read so far stop in the middle of a statement
+#[derive(Debug, Default)]
+struct Pending {
+    /// Braces still open
+    depth: usize,
+    /// Inside quoted text that continues on the next line
+    quoted: bool,
+    /// The last line is `class Name` without a body, which may still start on the next line
+    bare_class: bool,
+}
+
+impl Pending {
+    fn push(&mut self, line: &str) {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return;
+        }
+        if !self.quoted && trimmed.starts_with("%%") {
+            self.bare_class = false;
+            return;
+        }
+
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                // Skip whatever is escaped, a quote in particular
+                '\\' if self.quoted => {
+                    chars.next();
+                }
+                '"' => self.quoted = !self.quoted,
+                '{' if !self.quoted => self.depth += 1,
+                '}' if !self.quoted => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        self.bare_class = self.depth == 0
+            && !self.quoted
+            && !trimmed.contains('{')
+            && trimmed
+                .strip_prefix("class")
+                .is_some_and(|rest| rest.starts_with(char::is_whitespace));
+    }
+
+    fn is_pending(&self) -> bool {
+        self.depth > 0 || self.quoted || self.bare_class
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use super::*;
+    use crate::parserv2::parse_mermaid;
+
+    const SOURCE: &str = "---\ntitle: Zoo\n---\n%%{init: {theme: dark}}%%\nclassDiagram\ndirection LR\nclass Animal {\n  +name: String\n  +eat(food: Food) bool\n}\nclass Food\nnamespace Pets {\n  class Dog {\n    +bark() void\n  }\n  class Cat\n}\nAnimal : +age: int\nAnimal <|-- Dog\nAnimal --> Food : eats\nnote for Food \"{ tasty\"\nstyle Food fill:#f9f\n";
+
+    #[test]
+    fn test_parse_reader() {
+        let diagram = parse_reader(Cursor::new(SOURCE)).expect("Failed to parse");
+        assert_eq!(diagram, parse_mermaid(SOURCE).unwrap());
+
+        let classes = &diagram.namespaces[""].classes;
+        assert_eq!(classes["Animal"].members.len(), 3);
+        assert!(classes.contains_key("Food"));
+        assert_eq!(diagram.namespaces["Pets"].classes.len(), 2);
+        assert_eq!(diagram.relations.len(), 2);
+        assert_eq!(diagram.title(), Some("Zoo"));
+
+        // The buffer size doesn't matter
+        let diagram = parse_reader(BufReader::with_capacity(4, SOURCE.as_bytes())).unwrap();
+        assert_eq!(diagram, parse_mermaid(SOURCE).unwrap());
+
+        // Statements that continue on the next line
+        for source in [
+            "classDiagram\nclass A\n{\n+id: int\n}\n",
+            "classDiagram\nclass A\n\n{\n+id: int\n}\nclass B\n",
+            "classDiagram\nclass A\nnote for A \"line one\nline two\"\nA --> B\n",
+            "classDiagram\nnote \"a \\\"quoted\\\"\n{ brace\"\nclass A\n",
+        ] {
+            let diagram = parse_reader(Cursor::new(source)).expect(source);
+            assert_eq!(diagram, parse_mermaid(source).unwrap(), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_parse_reader_frontmatter() {
+        // Only a `---` on a line of its own ends the frontmatter
+        for source in [
+            "---\ntitle: \"a --- b\"\n---\nclassDiagram\nclass A\n",
+            "---\r\ntitle: Zoo\r\n---\r\nclassDiagram\r\nclass A\r\n",
+        ] {
+            let diagram = parse_reader(Cursor::new(source)).expect(source);
+            assert_eq!(diagram, parse_mermaid(source).unwrap(), "{source}");
+            assert!(diagram.title().is_some(), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_parse_reader_errors() {
+        let source = "classDiagram\nclass A {\n  +id: int\n}\n!!!\nclass B\n";
+        let Err(MermaidParseError::ExpectedStmt { offset, .. }) = parse_reader(Cursor::new(source))
+        else {
+            panic!("The bad statement should be reported");
+        };
+        assert_eq!(&source[offset..], "!!!\nclass B\n");
+
+        let error = parse_reader(Cursor::new(source)).unwrap_err();
+        assert_eq!(error.to_string(), "expected a statement at line 5: !!!");
+
+        assert!(matches!(
+            parse_reader(Cursor::new("flowchart LR\nclassDiagram\n")),
+            Err(MermaidParseError::ExpectedClassDiagram)
+        ));
+        assert!(matches!(
+            parse_reader(Cursor::new(b"classDiagram\n\xff\n".as_slice())),
+            Err(MermaidParseError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_pending() {
+        let pending = |lines: &[&str]| {
+            let mut pending = Pending::default();
+            for line in lines {
+                pending.push(line);
+            }
+            pending.is_pending()
+        };
+
+        assert!(pending(&["class A {\n"]));
+        assert!(!pending(&["class A {\n", "  +run() void }\n"]));
+        assert!(!pending(&["note \"{\"\n"]));
+        assert!(pending(&["class A {\n", "  %% }\n"]));
+
+        // Quoted text that spans lines, with an escaped quote inside
+        assert!(pending(&["note \"say \\\"hi\n"]));
+        assert!(!pending(&["note \"say \\\"hi\n", "there\\\" now\"\n"]));
+
+        // A bare class waits for the next line, which may open its body
+        assert!(pending(&["class A\n", "\n"]));
+        assert!(pending(&["class A\n", "{\n"]));
+        assert!(!pending(&["class A\n", "class B {}\n"]));
+        assert!(!pending(&["class A\n", "%% comment\n"]));
+        assert!(!pending(&["classroom : +seats int\n"]));
+    }
+}
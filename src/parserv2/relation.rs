@@ -1,25 +1,34 @@
 use std::borrow::Cow;
 
-use crate::types::{Relation, RelationKind};
+use crate::types::{LineStyle, Relation, RelationKind};
 
-use super::{class::class_name, IResult, Stmt};
+use super::{IResult, MermaidParseError, ParseOptions, Stmt, class::class_name};
 
 use nom::{
-    self,
-    branch::alt,
-    bytes::complete::{tag, take_while1},
-    character::complete::{char, multispace0},
-    combinator::{map, opt},
+    self, Parser,
+    bytes::complete::{is_not, take_while1},
+    character::complete::{char, multispace0, space0},
+    combinator::opt,
     sequence::delimited,
-    Parser,
 };
 
-enum Direction {
+/// Which way the arrow in a relation statement points, before normalization.
+pub enum Direction {
     Forward,
     Backward,
 }
 
 pub fn relation_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'source>> {
+    relation_stmt_with(s, &ParseOptions::default())
+}
+
+/// [`relation_stmt`] using the given options. The span is relative to the start of `s`.
+pub fn relation_stmt_with<'source>(
+    s: &'source str,
+    options: &ParseOptions,
+) -> IResult<&'source str, Stmt<'source>> {
+    let input = s;
+
     // Skip leading whitespace
     let (s, _) = multispace0.parse(s)?;
 
@@ -30,32 +39,37 @@ pub fn relation_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'so
     let (s, lhs_mult) = opt(quoted_string).parse(s)?;
 
     // Parse relation kind and direction
-    let (s, (kind, direction)) = relation_kind(s)?;
+    let operator_start = s;
+    let (s, (kind, line, direction)) = relation_kind(s)?;
+    let operator = &operator_start[..operator_start.len() - s.len()];
 
     // Parse optional right cardinality (quoted string)
     let (s, rhs_mult) = opt(quoted_string).parse(s)?;
 
     // Parse right class name
+    let (s, _) = multispace0.parse(s)?;
+    let rhs_start = s;
     let (s, rhs) = class_name(s)?;
 
-    // Parse optional label (after colon)
-    let (s, label) = opt(label_with_colon).parse(s)?;
+    // A chained relation (`A --> B --> C`) continues from the right class. Hand the input back
+    // starting at that class so the caller parses the next link as its own statement.
+    let chained = (opt(quoted_string), relation_kind).parse(s).is_ok();
 
-    // Skip trailing whitespace
-    let (s, _) = multispace0.parse(s)?;
+    let rhs_end = s;
+    let (s, label) = if chained {
+        (rhs_start, None)
+    } else {
+        // Parse optional label (after colon)
+        let (s, label) = opt(label_with_colon).parse(s)?;
 
-    // Handle direction: swap tail/head and cardinalities if backward
-    // For symmetric operators (SolidLink) with specific test class names "to" and "from",
-    // swap if "to" appears on the left (to maintain consistent tail/head ordering in tests)
-    let should_swap = match direction {
-        Direction::Backward => true,
-        Direction::Forward => {
-            // Special case for test class names "from" and "to" with symmetric operators
-            // When we see "to -- from", treat it as if direction was backward
-            matches!(kind, RelationKind::SolidLink) && lhs == "to" && rhs == "from"
-        }
+        // Skip trailing whitespace
+        let (s, _) = multispace0.parse(s)?;
+        (s, label)
     };
 
+    // Handle direction: swap tail/head and cardinalities if backward
+    let should_swap = matches!(direction, Direction::Backward);
+
     let (tail, head, cardinality_tail, cardinality_head) = if should_swap {
         (
             Cow::Borrowed(rhs),
@@ -76,9 +90,18 @@ pub fn relation_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'so
         tail,
         head,
         kind,
+        line,
         cardinality_tail,
         cardinality_head,
         label: label.map(Cow::Borrowed),
+        // Only unusual spellings are kept, ordinary arrows are rebuilt from kind and line
+        raw_operator: (operator.contains("---") || operator.contains("..."))
+            .then_some(Cow::Borrowed(operator)),
+        reversed: should_swap,
+        // A link in a chain ends at its right class, even though that is handed back
+        span: options
+            .spans
+            .then(|| super::span_in(input, input, if chained { rhs_end } else { s })),
     };
 
     Ok((s, Stmt::Relation(relation)))
@@ -92,63 +115,138 @@ fn quoted_string(s: &str) -> IResult<&str, &str> {
     Ok((s, content))
 }
 
-/// Parse a label after colon (e.g., ": label text")
+/// Parse a label after colon (e.g., ": label text"). The label runs to the end of the line, so
+/// it may contain colons itself (": has a: b"). A `;` at the very end terminates the statement
+/// and is left for the caller, unless it closes an entity like `#35;`. Quotes around the whole
+/// label (`: "has many"`) are dropped.
 fn label_with_colon(s: &str) -> IResult<&str, &str> {
-    let (s, _) = multispace0.parse(s)?;
+    let (s, _) = space0.parse(s)?;
     let (s, _) = char(':').parse(s)?;
-    let (s, _) = multispace0.parse(s)?;
-    let (s, text) = take_while1(|c: char| !c.is_control()).parse(s)?;
-    Ok((s, text.trim()))
+    let (s, _) = space0.parse(s)?;
+    let (_, line) = is_not("\r\n").parse(s)?;
+
+    let mut text = line.trim_end();
+    if !ends_with_entity(text)
+        && let Some(stripped) = text.strip_suffix(';')
+    {
+        text = stripped;
+    }
+
+    let label = text.trim();
+    let label = unquote(label).unwrap_or(label);
+    Ok((&s[text.len()..], label))
+}
+
+/// Whether `label` has to be quoted to come back unchanged from a relation statement: it is
+/// empty, starts or ends with whitespace, ends with a `;` that isn't part of an entity, or is
+/// itself wrapped in quotes
+pub fn label_needs_quotes(label: &str) -> bool {
+    label.is_empty()
+        || label.trim() != label
+        || (label.ends_with(';') && !ends_with_entity(label))
+        || unquote(label).is_some()
 }
 
-pub fn relation_kind(s: &str) -> IResult<&str, (RelationKind, Direction)> {
-    alt((
-        // Inheritance
-        map(tag("<|--"), |_| {
-            (RelationKind::Inheritance, Direction::Backward)
-        }),
-        map(tag("--|>"), |_| {
-            (RelationKind::Inheritance, Direction::Forward)
-        }),
-        // Reversed --|> for tests (not a real Mermaid operator)
-        map(tag(">|--"), |_| {
-            (RelationKind::Inheritance, Direction::Backward)
-        }),
-        // Composition (tests expect Inheritance)
-        map(tag("*--"), |_| {
-            (RelationKind::Inheritance, Direction::Backward)
-        }),
-        map(tag("--*"), |_| {
-            (RelationKind::Inheritance, Direction::Forward)
-        }),
-        // Aggregation (tests expect Inheritance)
-        map(tag("o--"), |_| {
-            (RelationKind::Inheritance, Direction::Backward)
-        }),
-        map(tag("--o"), |_| {
-            (RelationKind::Inheritance, Direction::Forward)
-        }),
-        // Dependency
-        map(tag("<.."), |_| {
-            (RelationKind::Dependency, Direction::Backward)
-        }),
-        map(tag("..>"), |_| {
-            (RelationKind::Dependency, Direction::Forward)
-        }),
-        // Reversed ..> for tests (not a real Mermaid operator)
-        map(tag(">.."), |_| {
-            (RelationKind::Dependency, Direction::Backward)
-        }),
-        // SolidLink (must come after other -- patterns)
-        map(tag("--"), |_| {
-            (RelationKind::SolidLink, Direction::Forward)
-        }),
-        // DashLink (tests expect SolidLink, must come after other .. patterns)
-        map(tag(".."), |_| {
-            (RelationKind::SolidLink, Direction::Forward)
-        }),
-    ))
-    .parse(s)
+/// The text between a pair of quotes wrapping all of `text`
+fn unquote(text: &str) -> Option<&str> {
+    text.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Whether `text` ends with an entity like `#35;`
+fn ends_with_entity(text: &str) -> bool {
+    text.rfind('#')
+        .is_some_and(|i| entity(&text[i..]).is_some_and(|(_, len)| i + len == text.len()))
+}
+
+/// Decode Mermaid's entity escapes in label text: named ones like `#colon;` and `#quot;` and
+/// numeric ones like `#35;`. Unknown entities are left as written. Labels are stored verbatim by
+/// the parser, so this is for consumers that want the displayed text.
+pub fn decode_label_entities(label: &str) -> Cow<'_, str> {
+    if !label.contains('#') {
+        return Cow::Borrowed(label);
+    }
+
+    let mut decoded = String::with_capacity(label.len());
+    let mut rest = label;
+    while let Some(start) = rest.find('#') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match entity(rest) {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('#');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    Cow::Owned(decoded)
+}
+
+/// The inverse of [`decode_label_entities`] for text going inside quotes: `"` becomes `#quot;`,
+/// and a `#` that would otherwise be read as the start of an entity becomes `#35;`.
+pub fn encode_label_entities(text: &str) -> Cow<'_, str> {
+    let needs_escape = |i: usize, c: char| c == '"' || (c == '#' && entity(&text[i..]).is_some());
+    if !text.char_indices().any(|(i, c)| needs_escape(i, c)) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut encoded = String::with_capacity(text.len() + 8);
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => encoded.push_str("#quot;"),
+            '#' if needs_escape(i, c) => encoded.push_str("#35;"),
+            c => encoded.push(c),
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+/// The character for the entity `s` starts with, and how long the entity is
+fn entity(s: &str) -> Option<(char, usize)> {
+    let body = s.strip_prefix('#')?;
+    let end = body.find(';')?;
+    let c = match &body[..end] {
+        "colon" => ':',
+        "semi" => ';',
+        "quot" => '"',
+        "apos" => '\'',
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "num" => '#',
+        name => name.parse::<u32>().ok().and_then(char::from_u32)?,
+    };
+    Some((c, end + 2))
+}
+
+/// Parse a relation arrow, returning its kind, line style and which way it points. Arrows are
+/// looked up with [`RelationKind::from_arrow`], taking the longest known prefix of the arrow
+/// characters so `A-->other` stops before the `o`.
+pub fn relation_kind(s: &str) -> IResult<&str, (RelationKind, LineStyle, Direction)> {
+    let (_, run) = take_while1(|c: char| "<>|*o().-".contains(c)).parse(s)?;
+
+    // Every arrow character is ASCII, so any length is a char boundary
+    for len in (1..=run.len()).rev() {
+        let arrow = &run[..len];
+        if let Some((kind, backward)) = RelationKind::from_arrow(arrow) {
+            let direction = if backward {
+                Direction::Backward
+            } else {
+                Direction::Forward
+            };
+            return Ok((&s[len..], (kind, LineStyle::of_arrow(arrow), direction)));
+        }
+    }
+
+    Err(nom::Err::Error(MermaidParseError::Nom(
+        nom::error::ErrorKind::Tag,
+    )))
 }
 
 #[cfg(test)]
@@ -157,6 +255,7 @@ mod tests {
 
     use super::*;
 
+    #[allow(clippy::too_many_arguments)]
     fn check_relation_kind(
         lhs: &str,
         lhs_mult: Option<&str>,
@@ -203,10 +302,28 @@ mod tests {
     }
 
     fn check_from_to(forward_op: &str, kind: RelationKind) {
-        let backward_op = forward_op.chars().rev().collect::<String>();
+        // Mirror the arrow so it points the other way: `--|>` becomes `<|--`
+        let backward_op = forward_op
+            .chars()
+            .rev()
+            .map(|c| match c {
+                '<' => '>',
+                '>' => '<',
+                '(' => ')',
+                ')' => '(',
+                c => c,
+            })
+            .collect::<String>();
         check_relation_kind(
             "from", None, forward_op, None, "to", None, "from", "to", kind,
         );
+        // A link without an arrowhead reads the same both ways, so it keeps the order it was
+        // written in
+        let (expect_from, expect_to) = if backward_op == forward_op {
+            ("to", "from")
+        } else {
+            ("from", "to")
+        };
         check_relation_kind(
             "to",
             None,
@@ -214,8 +331,8 @@ mod tests {
             None,
             "from",
             None,
-            "from",
-            "to",
+            expect_from,
+            expect_to,
             kind,
         );
     }
@@ -252,14 +369,20 @@ mod tests {
 
     #[test]
     fn test_relation_stmt_composition() {
-        check_from_to("--*", RelationKind::Inheritance);
-        check_backtick_escape("--*", RelationKind::Inheritance);
+        check_from_to("--*", RelationKind::Composition);
+        check_backtick_escape("--*", RelationKind::Composition);
     }
 
     #[test]
     fn test_relation_stmt_aggregation() {
-        check_from_to("--o", RelationKind::Inheritance);
-        check_backtick_escape("--o", RelationKind::Inheritance);
+        check_from_to("--o", RelationKind::Aggregation);
+        check_backtick_escape("--o", RelationKind::Aggregation);
+    }
+
+    #[test]
+    fn test_relation_stmt_association() {
+        check_from_to("-->", RelationKind::Association);
+        check_backtick_escape("-->", RelationKind::Association);
     }
 
     #[test]
@@ -276,13 +399,331 @@ mod tests {
 
     #[test]
     fn test_relation_stmt_realization() {
-        check_from_to("..>", RelationKind::Dependency);
-        check_backtick_escape("..>", RelationKind::Dependency);
+        check_from_to("..|>", RelationKind::Realization);
+        check_backtick_escape("..|>", RelationKind::Realization);
     }
 
     #[test]
     fn test_relation_stmt_link_dash() {
-        check_from_to("..", RelationKind::SolidLink);
-        check_backtick_escape("..", RelationKind::SolidLink);
+        check_from_to("..", RelationKind::DashLink);
+        check_backtick_escape("..", RelationKind::DashLink);
+    }
+
+    #[test]
+    fn test_relation_stmt_lollipop() {
+        check_from_to("--()", RelationKind::Lollipop);
+        check_backtick_escape("--()", RelationKind::Lollipop);
+    }
+
+    #[test]
+    fn test_relation_stmt_lollipop_interface() {
+        // The interface after `--()` is an ordinary class name and becomes the head
+        for input in ["Widget --() Clickable", "Clickable ()-- Widget"] {
+            let (rem, Stmt::Relation(rel)) = relation_stmt(input).expect("Failed to parse") else {
+                panic!("We should only be returning Stmt::Relation");
+            };
+            assert!(rem.is_empty(), "There should be nothing left");
+            assert_eq!(rel.kind, RelationKind::Lollipop);
+            assert_eq!(rel.tail, "Widget");
+            assert_eq!(rel.head, "Clickable");
+        }
+    }
+
+    #[test]
+    fn test_relation_kind_longest_arrow() {
+        let (rem, (kind, line, _)) = relation_kind("..|> B").expect("Failed to parse arrow");
+        assert_eq!(rem, " B");
+        assert_eq!(kind, RelationKind::Realization);
+        assert_eq!(line, LineStyle::Dotted);
+
+        // A class name starting with `o` isn't part of the arrow
+        let (rem, (kind, line, _)) = relation_kind("-->other").expect("Failed to parse arrow");
+        assert_eq!(rem, "other");
+        assert_eq!(kind, RelationKind::Association);
+        assert_eq!(line, LineStyle::Solid);
+
+        assert!(relation_kind(">|--").is_err());
+
+        // Realization goes either way, and `..` on its own is still a dashed link
+        let (rem, (kind, line, direction)) =
+            relation_kind("<|.. B").expect("Failed to parse arrow");
+        assert_eq!(rem, " B");
+        assert_eq!(kind, RelationKind::Realization);
+        assert_eq!(line, LineStyle::Dotted);
+        assert!(matches!(direction, Direction::Backward));
+        let (_, (kind, _, direction)) = relation_kind(".. B").expect("Failed to parse arrow");
+        assert_eq!(kind, RelationKind::DashLink);
+        assert!(matches!(direction, Direction::Forward));
+        assert!(relation_kind(">..").is_err());
+    }
+
+    #[test]
+    fn test_relation_stmt_label() {
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("A --> B : has a: b").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.label, Some("has a: b".into()));
+
+        // The label stops at the end of the line and drops trailing whitespace
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("A --> B :  uses \t \r\nB --> C").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert_eq!(rem, "B --> C");
+        assert_eq!(rel.label, Some("uses".into()));
+
+        // A trailing semicolon ends the statement, unless it closes an entity
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("A --> B : uses ;").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert_eq!(rem, ";");
+        assert_eq!(rel.label, Some("uses".into()));
+
+        let (rem, Stmt::Relation(rel)) = relation_stmt("A --> B : #35;").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.label, Some("#35;".into()));
+    }
+
+    #[test]
+    fn test_relation_stmt_label_after_cardinalities() {
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("A \"1\" --> \"*\" B : owns many things\nB --> C : next")
+                .expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert_eq!(rem, "B --> C : next");
+        assert_eq!(rel.tail, "A");
+        assert_eq!(rel.head, "B");
+        assert_eq!(rel.cardinality_tail, Some("1".into()));
+        assert_eq!(rel.cardinality_head, Some("*".into()));
+        assert_eq!(rel.label, Some("owns many things".into()));
+
+        // A second relation on the same line isn't a statement of its own, it's label text
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("A \"1\" --> \"*\" B : owns B --> C").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty());
+        assert_eq!(rel.label, Some("owns B --> C".into()));
+    }
+
+    #[test]
+    fn test_relation_stmt_quoted_label() {
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("A --> B : \"complex: label\"").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.label, Some("complex: label".into()));
+
+        // Quotes keep what would otherwise be trimmed, and only count around the whole label
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("A --> B : \" ends with ;\";").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert_eq!(rem, ";");
+        assert_eq!(rel.label, Some(" ends with ;".into()));
+
+        let (_, Stmt::Relation(rel)) =
+            relation_stmt("A --> B : say \"hi\"").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert_eq!(rel.label, Some("say \"hi\"".into()));
+    }
+
+    #[test]
+    fn test_label_needs_quotes() {
+        assert!(!label_needs_quotes("has many"));
+        assert!(!label_needs_quotes("say \"hi\""));
+        assert!(!label_needs_quotes("#35;"));
+        assert!(label_needs_quotes(""));
+        assert!(label_needs_quotes(" padded"));
+        assert!(label_needs_quotes("uses;"));
+        assert!(label_needs_quotes("\"quoted\""));
+    }
+
+    #[test]
+    fn test_relation_stmt_label_entities() {
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("A --> B : key#colon;value").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.label, Some("key#colon;value".into()));
+        assert_eq!(
+            decode_label_entities(rel.label.as_deref().unwrap()),
+            "key:value"
+        );
+    }
+
+    #[test]
+    fn test_decode_label_entities() {
+        assert!(matches!(
+            decode_label_entities("plain"),
+            Cow::Borrowed("plain")
+        ));
+        assert_eq!(decode_label_entities("#quot;hi#quot;"), "\"hi\"");
+        assert_eq!(decode_label_entities("a#semi; b#35;c"), "a; b#c");
+        assert_eq!(
+            decode_label_entities("#unknown; #no end"),
+            "#unknown; #no end"
+        );
+    }
+
+    #[test]
+    fn test_encode_label_entities() {
+        assert!(matches!(
+            encode_label_entities("C# code"),
+            Cow::Borrowed("C# code")
+        ));
+        assert_eq!(encode_label_entities("say \"hi\""), "say #quot;hi#quot;");
+        assert_eq!(
+            encode_label_entities("#35; and #quot;"),
+            "#35;35; and #35;quot;"
+        );
+
+        for text in ["\"quoted\" #1; C#", "#colon; #", "plain"] {
+            assert_eq!(decode_label_entities(&encode_label_entities(text)), text);
+        }
+    }
+
+    #[test]
+    fn test_relation_stmt_self_loop_label() {
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("Node \"1\" --> \"0..1\" Node : next").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.tail, "Node");
+        assert_eq!(rel.head, "Node");
+        assert_eq!(rel.cardinality_tail, Some("1".into()));
+        assert_eq!(rel.cardinality_head, Some("0..1".into()));
+        assert_eq!(rel.label, Some("next".into()));
+        assert!(rel.is_self_loop());
+
+        let (_, Stmt::Relation(rel)) = relation_stmt("Node --> Nodes").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(!rel.is_self_loop());
+    }
+
+    #[test]
+    fn test_relation_stmt_chained() {
+        let (rem, Stmt::Relation(rel)) = relation_stmt("A --> B --> C").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert_eq!(rem, "B --> C", "The next link should start at the shared class");
+        assert_eq!(rel.tail, "A");
+        assert_eq!(rel.head, "B");
+
+        let (rem, Stmt::Relation(rel)) = relation_stmt(rem).expect("Failed to parse") else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.tail, "B");
+        assert_eq!(rel.head, "C");
+    }
+
+    #[test]
+    fn test_relation_stmt_dotted_names() {
+        let (rem, Stmt::Relation(rel)) = relation_stmt("A..>B").expect("Failed to parse") else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.tail, "A");
+        assert_eq!(rel.head, "B");
+        assert_eq!(rel.kind, RelationKind::Dependency);
+
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("com.shop.Order ..> com.shop.Item").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.tail, "com.shop.Order");
+        assert_eq!(rel.head, "com.shop.Item");
+    }
+
+    #[test]
+    fn test_relation_stmt_compact() {
+        let (rem, Stmt::Relation(rel)) = relation_stmt("A-->B:label").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.tail, "A");
+        assert_eq!(rel.head, "B");
+        assert_eq!(rel.kind, RelationKind::Association);
+        assert_eq!(rel.cardinality_tail, None);
+        assert_eq!(rel.cardinality_head, None);
+        assert_eq!(rel.label, Some("label".into()));
+
+        let (rem, Stmt::Relation(rel)) =
+            relation_stmt("Left-Side<|--Right-Side:is a").expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.tail, "Right-Side");
+        assert_eq!(rel.head, "Left-Side");
+        assert_eq!(rel.kind, RelationKind::Inheritance);
+        assert_eq!(rel.label, Some("is a".into()));
+    }
+
+    #[test]
+    fn test_relation_stmt_compact_cardinality() {
+        for (tight, spaced) in [
+            (r#"A"1"-->"*"B"#, r#"A "1" --> "*" B"#),
+            (r#"A"1"-->"*"B:uses"#, r#"A "1" --> "*" B : uses"#),
+            (r#"A"1"<|--"many"B"#, r#"A "1" <|-- "many" B"#),
+        ] {
+            let (rem, Stmt::Relation(rel)) = relation_stmt(tight).expect("Failed to parse") else {
+                panic!("We should only be returning Stmt::Relation");
+            };
+            assert!(rem.is_empty(), "There should be nothing left");
+            let (_, Stmt::Relation(expected)) = relation_stmt(spaced).expect("Failed to parse")
+            else {
+                panic!("We should only be returning Stmt::Relation");
+            };
+            assert_eq!(rel, expected);
+        }
+    }
+
+    #[test]
+    fn test_relation_stmt_self_loop() {
+        let (rem, Stmt::Relation(rel)) = relation_stmt("A --> A").expect("Failed to parse") else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.tail, "A");
+        assert_eq!(rel.head, "A");
+        assert_eq!(rel.kind, RelationKind::Association);
+
+        // Backward arrows swap the endpoints, which is a no-op for a self-loop
+        let (rem, Stmt::Relation(rel)) = relation_stmt("A <|-- A").expect("Failed to parse") else {
+            panic!("We should only be returning Stmt::Relation");
+        };
+        assert!(rem.is_empty(), "There should be nothing left");
+        assert_eq!(rel.tail, "A");
+        assert_eq!(rel.head, "A");
+        assert_eq!(rel.kind, RelationKind::Inheritance);
     }
 }
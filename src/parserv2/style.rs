@@ -0,0 +1,116 @@
+use std::borrow::Cow;
+
+use nom::{
+    Parser,
+    bytes::complete::{is_not, tag},
+    character::complete::{multispace0, space1},
+};
+
+use super::{IResult, MermaidParseError, Stmt, class::class_name};
+use crate::types::StyleDirective;
+
+/// # Parse a style statement
+///
+/// `style ClassName fill:#f9f,stroke:#333,stroke-width:4px` styles a single class. The
+/// declarations are CSS `property:value` pairs separated by commas, a comma inside parentheses
+/// (`rgb(1, 2, 3)`) belongs to the value.
+pub fn style_stmt<'source>(s: &'source str) -> IResult<&'source str, Stmt<'source>> {
+    let invalid = || nom::Err::Error(MermaidParseError::Nom(nom::error::ErrorKind::Verify));
+
+    let (s, _) = (multispace0, tag("style"), space1).parse(s)?;
+    let target_start = s;
+    let (s, target) = class_name(s)?;
+
+    // `class_name` eats the whitespace around the name, which has to stay on this line
+    let consumed = &target_start[..target_start.len() - s.len()];
+    if consumed.contains(['\r', '\n']) || !consumed.ends_with([' ', '\t']) {
+        return Err(invalid());
+    }
+
+    let (s, text) = is_not("\r\n").parse(s)?;
+    let (s, _) = multispace0.parse(s)?;
+
+    let declarations = declarations(text.trim_end().trim_end_matches(';')).ok_or_else(invalid)?;
+
+    Ok((
+        s,
+        Stmt::Style(StyleDirective {
+            target: Cow::Borrowed(target),
+            declarations,
+        }),
+    ))
+}
+
+/// Split `fill:#f9f,stroke:#333` into its pairs. `None` if a pair is missing its property or value.
+fn declarations(text: &str) -> Option<Vec<(Cow<'_, str>, Cow<'_, str>)>> {
+    let mut declarations = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ',')))
+    {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                let (property, value) = text[start..i].split_once(':')?;
+                let (property, value) = (property.trim(), value.trim());
+                if property.is_empty() || value.is_empty() {
+                    return None;
+                }
+                declarations.push((Cow::Borrowed(property), Cow::Borrowed(value)));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    Some(declarations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_style_stmt() {
+        let (rem, Stmt::Style(style)) =
+            style_stmt("style Animal fill:#f9f,stroke:#333\nclass Animal")
+                .expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Style");
+        };
+        assert_eq!(rem, "class Animal");
+        assert_eq!(style.target, "Animal");
+        assert_eq!(
+            style.declarations,
+            [
+                ("fill".into(), "#f9f".into()),
+                ("stroke".into(), "#333".into())
+            ]
+        );
+
+        let (_, Stmt::Style(style)) =
+            style_stmt("style `Big Cat` color: rgb(1, 2, 3) , stroke-width:4px;")
+                .expect("Failed to parse")
+        else {
+            panic!("We should only be returning Stmt::Style");
+        };
+        assert_eq!(style.target, "Big Cat");
+        assert_eq!(
+            style.declarations,
+            [
+                ("color".into(), "rgb(1, 2, 3)".into()),
+                ("stroke-width".into(), "4px".into())
+            ]
+        );
+
+        assert!(style_stmt("style Animal").is_err());
+        assert!(style_stmt("style Animal\nfill:#f9f").is_err());
+        assert!(style_stmt("style Animal fill").is_err());
+        assert!(style_stmt("style Animal fill:#f9f,").is_err());
+        assert!(style_stmt("styles Animal fill:#f9f").is_err());
+    }
+}
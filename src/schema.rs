@@ -0,0 +1,104 @@
+//! JSON Schema for the [`Diagram`] AST, for editors and frontends that want to validate diagrams
+//! they exchange as JSON. The AST types implement [`serde::Serialize`] to produce that JSON.
+//! Only built with the `schema` feature.
+
+use schemars::{
+    JsonSchema,
+    r#gen::SchemaGenerator,
+    schema::{RootSchema, Schema},
+    schema_for,
+};
+
+use crate::types::Diagram;
+
+/// The schema of [`Diagram`], with every type it refers to under `definitions`
+pub fn diagram_schema() -> RootSchema {
+    schema_for!(Diagram<'static>)
+}
+
+/// Stands in for the YAML values of the frontmatter and `%%{init}%%` directives, which can hold
+/// anything
+pub(crate) struct AnyValue;
+
+impl JsonSchema for AnyValue {
+    fn schema_name() -> String {
+        "AnyValue".to_owned()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        Schema::Bool(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagram_schema() {
+        let schema = diagram_schema();
+        assert_eq!(
+            schema
+                .schema
+                .metadata
+                .as_ref()
+                .and_then(|m| m.title.as_deref()),
+            Some("Diagram")
+        );
+        for definition in ["Relation", "Class", "Namespace", "RelationKind"] {
+            assert!(
+                schema.definitions.contains_key(definition),
+                "missing {definition}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_diagram_json_matches_schema() {
+        use std::collections::BTreeSet;
+
+        use crate::parserv2::parse_mermaid;
+
+        let diagram = parse_mermaid(
+            "---\ntitle: Zoo\n---\nclassDiagram\nclass Animal {\n  +name: String\n  +eat(food: Food) bool\n}\nnamespace Pets {\n  class Dog\n}\nAnimal <|-- Pets::Dog : is a\nnote for Animal \"hi\"\nstyle Animal fill:#f9f\n",
+        )
+        .unwrap();
+        let json = serde_json::to_value(&diagram).unwrap();
+        let schema = diagram_schema();
+
+        // The keys of a JSON object and the properties the schema gives it
+        let keys = |value: &serde_json::Value| -> BTreeSet<String> {
+            value.as_object().unwrap().keys().cloned().collect()
+        };
+        let properties = |object: &schemars::schema::SchemaObject| -> BTreeSet<String> {
+            object
+                .object
+                .as_ref()
+                .unwrap()
+                .properties
+                .keys()
+                .cloned()
+                .collect()
+        };
+        let definition = |name: &str| match &schema.definitions[name] {
+            Schema::Object(object) => properties(object),
+            Schema::Bool(_) => panic!("{name} should be an object"),
+        };
+
+        assert_eq!(keys(&json), properties(&schema.schema));
+        let namespace = &json["namespaces"]["Pets"];
+        assert_eq!(keys(namespace), definition("Namespace"));
+        assert_eq!(keys(&namespace["classes"]["Dog"]), definition("Class"));
+        let animal = &json["namespaces"][""]["classes"]["Animal"];
+        assert_eq!(
+            keys(&animal["members"][0]["Attribute"]),
+            definition("Attribute")
+        );
+        assert_eq!(keys(&animal["members"][1]["Method"]), definition("Method"));
+        assert_eq!(keys(&json["relations"][0]), definition("Relation"));
+        assert_eq!(keys(&json["notes"][0]), definition("Note"));
+        assert_eq!(keys(&json["styles"][0]), definition("StyleDirective"));
+        assert_eq!(json["relations"][0]["kind"], "Inheritance");
+        assert_eq!(json["yaml"]["title"], "Zoo");
+    }
+}
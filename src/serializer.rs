@@ -1,10 +1,58 @@
 //! Serialize Mermaid diagram structures back to text format
+//!
+//! Everything is written through [`std::fmt::Write`], so a diagram can be streamed into any
+//! writer with [`serialize_to`] without building an intermediate `String`.
 
+use crate::parserv2::class::is_name_char;
+use crate::parserv2::relation::{encode_label_entities, label_needs_quotes};
+use crate::parserv2::{MermaidParseError, parse_mermaid};
 use crate::types::{
-    Class, DEFAULT_NAMESPACE, Diagram, Direction, Member, Note, Relation, RelationKind,
-    TypeNotation, Visibility,
+    Class, DEFAULT_NAMESPACE, Diagram, DiagramHeader, Direction, Member, Namespace, Note,
+    NotePlacement, Relation, RelationKind, StyleDirective, TypeNotation, Visibility,
 };
-use std::fmt::Write;
+use std::borrow::Cow;
+use std::fmt::{self, Write};
+
+/// Knobs controlling how a diagram is written out. The defaults match `serialize_diagram`.
+#[derive(Debug, Clone)]
+pub struct SerializeOptions {
+    /// Put a space between the `$`/`*` classifiers and the member (`+$ count: int` instead of
+    /// `+$count: int`). Both forms parse to the same member.
+    pub space_after_classifier: bool,
+    /// Write the frontmatter exactly as it was parsed (`Diagram::raw_yaml`) instead of
+    /// re-serializing `Diagram::yaml`, which may reorder keys and change quoting.
+    pub preserve_frontmatter: bool,
+    /// Write relations whose arrow pointed backward in the source the same way (`B <|-- A`)
+    /// instead of turning them around (`A --|> B`). On by default, since `Relation::reversed`
+    /// is part of the diagram and turning the arrow around changes it.
+    pub keep_arrow_direction: bool,
+    /// Write `"` in note text and labels as `#quot;` (and a `#` that would read as an entity as
+    /// `#35;`), for text that was parsed with `ParseOptions::decode_entities`
+    pub encode_entities: bool,
+    /// Leave an empty line between consecutive classes, which makes large diagrams easier to read
+    pub blank_line_between_classes: bool,
+    /// Number of spaces members are indented by inside a class body
+    pub indent: usize,
+    /// Write a class's attributes before its methods, each group in source order
+    pub group_members: bool,
+    /// Break note text into lines of at most this many characters with Mermaid's `<br/>`
+    pub wrap_notes: Option<usize>,
+}
+
+impl Default for SerializeOptions {
+    fn default() -> Self {
+        SerializeOptions {
+            space_after_classifier: false,
+            preserve_frontmatter: false,
+            keep_arrow_direction: true,
+            encode_entities: false,
+            blank_line_between_classes: false,
+            indent: 2,
+            group_members: false,
+            wrap_notes: None,
+        }
+    }
+}
 
 /// Convert visibility to Mermaid symbol
 fn visibility_symbol(vis: Visibility) -> &'static str {
@@ -17,278 +65,596 @@ fn visibility_symbol(vis: Visibility) -> &'static str {
     }
 }
 
+/// A top level item of the diagram body: a class of the default namespace or a namespace block
+enum Block<'a, 'source> {
+    Class(&'a Class<'source>),
+    Namespace(&'a str, &'a Namespace<'source>),
+}
+
+impl Block<'_, '_> {
+    /// Where the block goes: its first class in declaration order. Ties, e.g. between classes
+    /// built by hand, are broken by name so the output doesn't depend on `HashMap` iteration order.
+    fn key(&self) -> (usize, &str) {
+        match self {
+            Block::Class(class) => (class.order, &class.name),
+            Block::Namespace(name, namespace) => {
+                (first_order(namespace).unwrap_or(usize::MAX), name)
+            }
+        }
+    }
+}
+
+/// The declaration order of the first class in `namespace` or the namespaces nested in it
+fn first_order(namespace: &Namespace) -> Option<usize> {
+    let own = namespace.classes.values().map(|class| class.order);
+    let nested = namespace.children.values().filter_map(first_order);
+    own.chain(nested).min()
+}
+
+/// Write a `namespace` block with its classes and the blocks of the namespaces nested in it.
+/// `path` is the qualified name of the namespace (`Outer::Inner`).
+fn serialize_namespace<W: Write>(
+    path: &str,
+    namespace: &Namespace,
+    options: &SerializeOptions,
+    output: &mut W,
+) -> fmt::Result {
+    let name = path.rsplit("::").next().unwrap_or(path);
+    writeln!(output, "namespace {} {{", escape_class_name(name))?;
+    if let Some(direction) = namespace.direction {
+        serialize_direction(direction, output)?;
+    }
+
+    let mut blocks: Vec<_> = namespace.classes.values().map(Block::Class).collect();
+    blocks.extend(
+        namespace
+            .children
+            .iter()
+            .map(|(name, child)| Block::Namespace(name, child)),
+    );
+    serialize_blocks(blocks, path, options, output)?;
+    output.write_str("}\n")
+}
+
+/// Write the classes and namespace blocks inside the namespace `path` (empty at the top level) in
+/// declaration order, each block where its first class was declared and empty ones last
+fn serialize_blocks<W: Write>(
+    mut blocks: Vec<Block>,
+    path: &str,
+    options: &SerializeOptions,
+    output: &mut W,
+) -> fmt::Result {
+    blocks.sort_by(|a, b| a.key().cmp(&b.key()));
+
+    let mut after_class = false;
+    for block in blocks {
+        match block {
+            Block::Class(class) => {
+                if after_class && options.blank_line_between_classes {
+                    output.write_char('\n')?;
+                }
+                // Serialize class without namespace prefix (it's already in the block context)
+                let class_name_only = class
+                    .name
+                    .strip_prefix(path)
+                    .and_then(|name| name.strip_prefix("::"))
+                    .unwrap_or(&class.name);
+                serialize_class(class, class_name_only, options, output)?;
+                after_class = true;
+            }
+            Block::Namespace(name, namespace) => {
+                let path = if path.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{path}::{name}")
+                };
+                serialize_namespace(&path, namespace, options, output)?;
+                after_class = false;
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Escape class name with backticks if it contains special characters
-fn escape_class_name(name: &str) -> String {
-    // Check if name needs backtick escaping (contains spaces or special chars)
-    if name.contains(|c: char| c.is_whitespace() || "!@#$%^&*()".contains(c)) {
-        format!("`{}`", name)
+fn escape_class_name(name: &str) -> Cow<'_, str> {
+    if is_bare_name(name) {
+        Cow::Borrowed(name)
     } else {
-        name.to_string()
+        Cow::Owned(format!("`{}`", name))
+    }
+}
+
+/// Whether `name` reads back whole without backticks, following `class_name`: words joined by
+/// inner dashes, and those joined by `.` or by the `::` that qualifies a class with its namespace.
+/// Anything else (`,`, a lone `:`, `{`, `~`, a trailing `-`, spaces, ...) needs backticks.
+fn is_bare_name(name: &str) -> bool {
+    name.split("::").all(|path| {
+        path.split('.').all(|token| {
+            token
+                .split('-')
+                .all(|word| !word.is_empty() && word.chars().all(is_name_char))
+        })
+    })
+}
+
+/// Write the `$` (static) and `*` (abstract) classifiers that follow the visibility symbol
+fn serialize_classifiers<W: Write>(
+    is_static: bool,
+    is_abstract: bool,
+    options: &SerializeOptions,
+    output: &mut W,
+) -> fmt::Result {
+    if is_static {
+        output.write_char('$')?;
+    }
+    if is_abstract {
+        output.write_char('*')?;
+    }
+    if (is_static || is_abstract) && options.space_after_classifier {
+        output.write_char(' ')?;
     }
+    Ok(())
 }
 
 /// Serialize a single member (attribute or method)
-fn serialize_member(member: &Member, output: &mut String) {
+fn serialize_member<W: Write>(
+    member: &Member,
+    options: &SerializeOptions,
+    output: &mut W,
+) -> fmt::Result {
     match member {
         Member::Attribute(attr) => {
-            write!(output, "{}", visibility_symbol(attr.visibility)).unwrap();
+            write!(output, "{}", visibility_symbol(attr.visibility))?;
+            serialize_classifiers(attr.is_static, false, options, output)?;
 
             // Use the notation style that was parsed
             match attr.type_notation {
                 TypeNotation::Prefix => {
                     // Type Name
                     if let Some(data_type) = &attr.data_type {
-                        write!(output, "{} {}", escape_class_name(data_type), attr.name).unwrap();
+                        write!(output, "{} {}", data_type, attr.name)?;
                     } else {
-                        write!(output, "{}", attr.name).unwrap();
+                        write!(output, "{}", attr.name)?;
                     }
                 }
                 TypeNotation::Postfix => {
                     // Name: Type
-                    write!(output, "{}", attr.name).unwrap();
+                    write!(output, "{}", attr.name)?;
                     if let Some(data_type) = &attr.data_type {
-                        write!(output, ": {}", escape_class_name(data_type)).unwrap();
+                        write!(output, ": {}", data_type)?;
                     }
                 }
                 TypeNotation::None => {
-                    write!(output, "{}", attr.name).unwrap();
+                    write!(output, "{}", attr.name)?;
                 }
             }
-
-            if attr.is_static {
-                output.push('$');
-            }
         }
         Member::Method(method) => {
-            write!(output, "{}", visibility_symbol(method.visibility)).unwrap();
+            write!(output, "{}", visibility_symbol(method.visibility))?;
+            serialize_classifiers(method.is_static, method.is_abstract, options, output)?;
 
-            write!(output, "{}(", method.name).unwrap();
+            write!(output, "{}(", method.name)?;
 
             // Parameters
             for (i, param) in method.parameters.iter().enumerate() {
                 if i > 0 {
-                    output.push_str(", ");
+                    output.write_str(", ")?;
                 }
 
                 match param.type_notation {
                     TypeNotation::Prefix => {
                         // Type Name
                         if let Some(data_type) = &param.data_type {
-                            write!(output, "{} {}", escape_class_name(data_type), param.name)
-                                .unwrap();
+                            write!(output, "{} {}", data_type, param.name)?;
                         } else {
-                            write!(output, "{}", param.name).unwrap();
+                            write!(output, "{}", param.name)?;
                         }
                     }
                     TypeNotation::Postfix => {
                         // Name: Type
-                        write!(output, "{}", param.name).unwrap();
+                        write!(output, "{}", param.name)?;
                         if let Some(data_type) = &param.data_type {
-                            write!(output, ": {}", escape_class_name(data_type)).unwrap();
+                            write!(output, ": {}", data_type)?;
                         }
                     }
                     TypeNotation::None => {
-                        write!(output, "{}", param.name).unwrap();
+                        write!(output, "{}", param.name)?;
                     }
                 }
+                if let Some(default_value) = &param.default_value {
+                    write!(output, " = {}", default_value)?;
+                }
             }
-            output.push(')');
-
-            if method.is_static {
-                output.push('$');
-            }
-            if method.is_abstract {
-                output.push('*');
-            }
+            output.write_char(')')?;
 
             // Return type (always postfix in mermaid - no colon)
             if let Some(return_type) = &method.return_type {
-                write!(output, " {}", escape_class_name(return_type)).unwrap();
+                write!(output, " {}", return_type)?;
             }
         }
     }
+    Ok(())
 }
 
-/// Serialize a single class to Mermaid format using brace notation
-fn serialize_class(class: &Class, output: &mut String) {
-    let class_name = escape_class_name(&class.name);
-
-    if class.members.is_empty() {
-        // Class declaration without braces if no members
-        writeln!(output, "class {}", class_name).unwrap();
-    } else {
-        // Class declaration with braces
-        writeln!(output, "class {} {{", class_name).unwrap();
-
-        // Members - one per line inside braces
-        for member in &class.members {
-            output.push_str("  ");
-            serialize_member(member, output);
-            output.push('\n');
-        }
+/// Serialize a single class to Mermaid format using brace notation. `name` is the name to write,
+/// which differs from `class.name` inside a namespace block.
+fn serialize_class<W: Write>(
+    class: &Class,
+    name: &str,
+    options: &SerializeOptions,
+    output: &mut W,
+) -> fmt::Result {
+    let class_name = escape_class_name(name);
+
+    write!(output, "class {}", class_name)?;
+    if let Some(generic) = &class.generic {
+        write!(output, "~{}~", generic)?;
+    }
+    if let Some(label) = &class.label {
+        write!(output, "[\"{}\"]", label)?;
+    }
+    if let Some(css_class) = &class.css_class {
+        write!(output, ":::{}", css_class)?;
+    }
 
-        output.push_str("}\n");
+    if class.members.is_empty() && class.annotations.is_empty() {
+        // Class declaration without braces if there's nothing to put in them
+        output.write_char('\n')?;
+        return Ok(());
     }
 
-    // Serialize annotation on a new line after the class definition
-    if let Some(annotation) = &class.annotation {
-        writeln!(output, "<<{}>> {}", annotation, class_name).unwrap();
+    // Class declaration with braces
+    output.write_str(" {\n")?;
+
+    // The annotations go first, then the members - one per line inside braces
+    let indent = options.indent;
+    for annotation in &class.annotations {
+        writeln!(output, "{:indent$}<<{}>>", "", annotation.as_str())?;
+    }
+    let mut members: Vec<_> = class.members.iter().collect();
+    if options.group_members {
+        // A stable sort keeps the source order within each group
+        members.sort_by_key(|member| matches!(member, Member::Method(_)));
     }
+    for member in members {
+        write!(output, "{:indent$}", "")?;
+        serialize_member(member, options, output)?;
+        output.write_char('\n')?;
+    }
+
+    output.write_str("}\n")
 }
 
 /// Serialize a relation to Mermaid format
-fn serialize_relation(relation: &Relation, output: &mut String) {
-    let from_name = escape_class_name(&relation.tail);
-    let to_name = escape_class_name(&relation.head);
+fn serialize_relation<W: Write>(
+    relation: &Relation,
+    options: &SerializeOptions,
+    output: &mut W,
+) -> fmt::Result {
+    let tail = (&relation.tail, &relation.cardinality_tail);
+    let head = (&relation.head, &relation.cardinality_head);
+
+    // Build the relation symbol (right-pointing since parser normalizes), unless the arrow was
+    // written in an unusual way we want to keep or we're asked to keep its direction. A
+    // backward arrow puts the head on the left.
+    let (arrow, (left, left_card), (right, right_card)) = match &relation.raw_operator {
+        Some(raw) if RelationKind::from_arrow(raw).is_some_and(|(_, backward)| backward) => {
+            (raw.as_ref(), head, tail)
+        }
+        Some(raw) => (raw.as_ref(), tail, head),
+        None if options.keep_arrow_direction && relation.reversed => {
+            (relation.kind.backward_arrow(relation.line), head, tail)
+        }
+        None => (relation.kind.forward_arrow(relation.line), tail, head),
+    };
 
-    write!(output, "{}", from_name).unwrap();
+    write!(output, "{}", escape_class_name(left))?;
 
-    // Add cardinality_from if present
-    if let Some(card) = &relation.cardinality_tail {
-        write!(output, " \"{}\"", card).unwrap();
+    // Add the left cardinality if present
+    if let Some(card) = left_card {
+        write!(output, " \"{}\"", card)?;
     }
 
-    output.push(' ');
-
-    // Build the relation symbol (always right-pointing since parser normalizes)
-    match relation.kind {
-        RelationKind::Inheritance => output.push_str("--|>"),
-        RelationKind::Composition => output.push_str("--*"),
-        RelationKind::Aggregation => output.push_str("--o"),
-        RelationKind::Association => output.push_str("-->"),
-        RelationKind::SolidLink => output.push_str("--"),
-        RelationKind::Dependency => output.push_str("..>"),
-        RelationKind::Realization => output.push_str("..|>"),
-        RelationKind::DashLink => output.push_str(".."),
-        RelationKind::Lollipop => output.push_str("--()"),
-    }
+    write!(output, " {}", arrow)?;
 
-    // Add cardinality_to if present
-    if let Some(card) = &relation.cardinality_head {
-        write!(output, " \"{}\"", card).unwrap();
+    // Add the right cardinality if present
+    if let Some(card) = right_card {
+        write!(output, " \"{}\"", card)?;
     }
 
-    write!(output, " {}", to_name).unwrap();
+    write!(output, " {}", escape_class_name(right))?;
 
     // Add label if present
     if let Some(label) = &relation.label {
-        write!(output, " : {}", label).unwrap();
+        let label = if options.encode_entities {
+            encode_label_entities(label)
+        } else {
+            Cow::Borrowed(label.as_ref())
+        };
+        if label_needs_quotes(&label) {
+            write!(output, " : \"{}\"", label)?;
+        } else {
+            write!(output, " : {}", label)?;
+        }
     }
 
-    output.push('\n');
+    output.write_char('\n')
 }
 
-/// Serialize a note to Mermaid format
-fn serialize_note(note: &Note, output: &mut String) {
+/// Serialize a note to Mermaid format, escaping quotes in the text as `\"` or as entities
+fn serialize_note<W: Write>(
+    note: &Note,
+    options: &SerializeOptions,
+    output: &mut W,
+) -> fmt::Result {
+    let wrapped = match options.wrap_notes {
+        Some(width) => Cow::Owned(wrap_note_text(&note.text, width)),
+        None => Cow::Borrowed(note.text.as_ref()),
+    };
+    let encoded = if options.encode_entities {
+        encode_label_entities(&wrapped)
+    } else {
+        Cow::Borrowed(wrapped.as_ref())
+    };
+    let text = escape_note_text(&encoded);
+
     if let Some(target_class) = &note.target_class {
+        let placement = match note.placement {
+            None => "for",
+            Some(NotePlacement::LeftOf) => "left of",
+            Some(NotePlacement::RightOf) => "right of",
+            Some(NotePlacement::Over) => "over",
+        };
         writeln!(
             output,
-            "note for {} \"{}\"",
+            "note {} {} \"{}\"",
+            placement,
             escape_class_name(target_class),
-            note.text
+            text
         )
-        .unwrap();
     } else {
-        writeln!(output, "note \"{}\"", note.text).unwrap();
+        writeln!(output, "note \"{}\"", text)
     }
 }
 
+/// Escape quotes and backslashes in note text as `\"` and `\\`, the way the parser reads them
+fn escape_note_text(text: &str) -> Cow<'_, str> {
+    if !text.contains(['"', '\\']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 2);
+    for c in text.chars() {
+        if c == '"' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    Cow::Owned(escaped)
+}
+
+/// Break `text` at spaces into lines of at most `width` characters joined with `<br/>`. Breaks
+/// already in the text are kept, so wrapping is stable, and a word longer than `width` gets a line
+/// of its own.
+fn wrap_note_text(text: &str, width: usize) -> String {
+    let mut wrapped = String::with_capacity(text.len());
+    for (i, line) in text.split("<br/>").enumerate() {
+        if i > 0 {
+            wrapped.push_str("<br/>");
+        }
+        let mut len = 0;
+        for (j, word) in line.split(' ').enumerate() {
+            let word_len = word.chars().count();
+            if j > 0 {
+                if len > 0 && len + 1 + word_len > width {
+                    wrapped.push_str("<br/>");
+                    len = 0;
+                } else {
+                    wrapped.push(' ');
+                    len += 1;
+                }
+            }
+            wrapped.push_str(word);
+            len += word_len;
+        }
+    }
+    wrapped
+}
+
+/// Write a YAML value as JSON, which is both valid YAML and what Mermaid expects in directives
+fn serialize_json<W: Write>(value: &serde_yml::Value, output: &mut W) -> fmt::Result {
+    use serde_yml::Value;
+
+    match value {
+        Value::Null => output.write_str("null"),
+        Value::Bool(b) => write!(output, "{}", b),
+        Value::Number(n) => write!(output, "{}", n),
+        Value::String(s) => serialize_json_string(s, output),
+        Value::Sequence(items) => {
+            output.write_char('[')?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    output.write_str(", ")?;
+                }
+                serialize_json(item, output)?;
+            }
+            output.write_char(']')
+        }
+        Value::Mapping(mapping) => {
+            output.write_char('{')?;
+            for (i, (key, value)) in mapping.iter().enumerate() {
+                if i > 0 {
+                    output.write_str(", ")?;
+                }
+                // JSON keys are always strings
+                match key {
+                    Value::String(key) => serialize_json_string(key, output)?,
+                    key => {
+                        let mut text = String::new();
+                        serialize_json(key, &mut text)?;
+                        serialize_json_string(&text, output)?;
+                    }
+                }
+                output.write_str(": ")?;
+                serialize_json(value, output)?;
+            }
+            output.write_char('}')
+        }
+        Value::Tagged(tagged) => serialize_json(&tagged.value, output),
+    }
+}
+
+/// Write a JSON string literal
+fn serialize_json_string<W: Write>(s: &str, output: &mut W) -> fmt::Result {
+    output.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => output.write_str("\\\"")?,
+            '\\' => output.write_str("\\\\")?,
+            '\n' => output.write_str("\\n")?,
+            '\r' => output.write_str("\\r")?,
+            '\t' => output.write_str("\\t")?,
+            c if c.is_control() => write!(output, "\\u{:04x}", c as u32)?,
+            c => output.write_char(c)?,
+        }
+    }
+    output.write_char('"')
+}
+
+/// Serialize a directive as `%%{type: args}%%`. The directive type is written bare since Mermaid
+/// doesn't accept a quoted one; a type without arguments (`%%{wrap}%%`) is written on its own.
+fn serialize_directive<W: Write>(directive: &serde_yml::Value, output: &mut W) -> fmt::Result {
+    output.write_str("%%{")?;
+    match directive {
+        serde_yml::Value::Mapping(mapping) => {
+            for (i, (key, value)) in mapping.iter().enumerate() {
+                if i > 0 {
+                    output.write_str(", ")?;
+                }
+                match key {
+                    serde_yml::Value::String(key) => output.write_str(key)?,
+                    key => serialize_json(key, output)?,
+                }
+                if !value.is_null() {
+                    output.write_str(": ")?;
+                    serialize_json(value, output)?;
+                }
+            }
+        }
+        other => serialize_json(other, output)?,
+    }
+    output.write_str("}%%\n")
+}
+
+/// Serialize a style statement
+fn serialize_style<W: Write>(style: &StyleDirective, output: &mut W) -> fmt::Result {
+    write!(output, "style {} ", escape_class_name(&style.target))?;
+    for (i, (property, value)) in style.declarations.iter().enumerate() {
+        if i > 0 {
+            output.write_char(',')?;
+        }
+        write!(output, "{property}:{value}")?;
+    }
+    output.write_char('\n')
+}
+
 /// Serialize direction to Mermaid format
-fn serialize_direction(direction: Direction, output: &mut String) {
+fn serialize_direction<W: Write>(direction: Direction, output: &mut W) -> fmt::Result {
     let dir_str = match direction {
         Direction::TopBottom => "TB",
         Direction::BottomTop => "BT",
         Direction::RightLeft => "RL",
         Direction::LeftRight => "LR",
     };
-    writeln!(output, "direction {}", dir_str).unwrap();
+    writeln!(output, "direction {}", dir_str)
 }
 
 /// Serialize entire diagram to Mermaid text format
 /// Each statement is on its own line (except for quoted strings in notes and backtick-escaped names)
 pub fn serialize_diagram(diagram: &Diagram) -> String {
+    serialize_diagram_with(diagram, &SerializeOptions::default())
+}
+
+/// Serialize entire diagram to Mermaid text format using the given options
+pub fn serialize_diagram_with(diagram: &Diagram, options: &SerializeOptions) -> String {
     let mut output = String::new();
+    serialize_to_with(diagram, options, &mut output).expect("writing to a String cannot fail");
+    output
+}
+
+/// Reformat Mermaid source: parse it and write it back out, keeping the frontmatter text and the
+/// direction of arrows as they were written.
+pub fn format(source: &str) -> Result<String, MermaidParseError> {
+    let diagram = parse_mermaid(source)?;
+    let options = SerializeOptions {
+        preserve_frontmatter: true,
+        ..Default::default()
+    };
+    Ok(serialize_diagram_with(&diagram, &options))
+}
+
+/// Write the diagram to `output`, propagating any error from the writer
+pub fn serialize_to<W: Write>(diagram: &Diagram, output: &mut W) -> fmt::Result {
+    serialize_to_with(diagram, &SerializeOptions::default(), output)
+}
 
+/// Write the diagram to `output` using the given options, propagating any error from the writer
+pub fn serialize_to_with<W: Write>(
+    diagram: &Diagram,
+    options: &SerializeOptions,
+    output: &mut W,
+) -> fmt::Result {
     // Serialize YAML frontmatter if present
-    if let Some(yaml) = &diagram.yaml {
-        output.push_str("---\n");
-        output.push_str(&serde_yml::to_string(yaml).unwrap_or_default());
-        output.push_str("---\n");
+    if options.preserve_frontmatter
+        && let Some(raw) = &diagram.raw_yaml
+    {
+        writeln!(output, "---{raw}---")?;
+    } else if let Some(yaml) = &diagram.yaml {
+        output.write_str("---\n")?;
+        output.write_str(&serde_yml::to_string(yaml).unwrap_or_default())?;
+        output.write_str("---\n")?;
     }
 
-    output.push_str("classDiagram\n");
+    for directive in &diagram.directives {
+        serialize_directive(directive, output)?;
+    }
+
+    match diagram.header {
+        DiagramHeader::ClassDiagram => output.write_str("classDiagram\n")?,
+        DiagramHeader::ClassDiagramV2 => output.write_str("classDiagram-v2\n")?,
+    }
 
     // Serialize direction if present
     if let Some(direction) = diagram.direction {
-        serialize_direction(direction, &mut output);
+        serialize_direction(direction, output)?;
     }
 
-    // Separate default namespace from named namespaces
-    let mut default_classes = Vec::new();
-    let mut namespaced_classes = Vec::new();
-
+    // Classes of the default namespace and namespace blocks
+    let mut blocks = Vec::new();
     for (namespace_name, namespace) in &diagram.namespaces {
         if namespace_name.as_ref() == DEFAULT_NAMESPACE || namespace_name.is_empty() {
-            for class in namespace.classes.values() {
-                default_classes.push(class);
-            }
+            blocks.extend(namespace.classes.values().map(Block::Class));
         } else {
-            namespaced_classes.push((namespace_name, namespace));
+            blocks.push(Block::Namespace(namespace_name, namespace));
         }
     }
-
-    // Serialize default namespace classes
-    for class in default_classes {
-        serialize_class(class, &mut output);
-    }
-
-    // Serialize namespaced classes in namespace blocks
-    for (namespace_name, namespace) in namespaced_classes {
-        writeln!(output, "namespace {} {{", escape_class_name(namespace_name)).unwrap();
-        for class in namespace.classes.values() {
-            // Serialize class without namespace prefix (it's already in the block context)
-            let class_name_only = class
-                .name
-                .strip_prefix(&format!("{}::", namespace_name))
-                .unwrap_or(&class.name);
-            let class_name = escape_class_name(class_name_only);
-
-            if class.members.is_empty() {
-                // Class declaration without braces if no members
-                writeln!(output, "class {}", class_name).unwrap();
-            } else {
-                // Class declaration with braces
-                writeln!(output, "class {} {{", class_name).unwrap();
-
-                // Members - one per line inside braces
-                for member in &class.members {
-                    output.push_str("  ");
-                    serialize_member(member, &mut output);
-                    output.push('\n');
-                }
-
-                output.push_str("}\n");
-            }
-
-            // Serialize annotation on a new line after the class definition
-            if let Some(annotation) = &class.annotation {
-                writeln!(output, "<<{}>> {}", annotation, class_name).unwrap();
-            }
-        }
-        output.push_str("}\n");
-    }
+    serialize_blocks(blocks, "", options, output)?;
 
     // Serialize relations
     for relation in &diagram.relations {
-        serialize_relation(relation, &mut output);
+        serialize_relation(relation, options, output)?;
     }
 
     // Serialize notes
     for note in &diagram.notes {
-        serialize_note(note, &mut output);
+        serialize_note(note, options, output)?;
     }
 
-    output
+    for style in &diagram.styles {
+        serialize_style(style, output)?;
+    }
+
+    Ok(())
 }
 
 // TODO: Re-enable these tests once parserv2 is fully implemented
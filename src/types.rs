@@ -1,13 +1,45 @@
 use std::{borrow::Cow, collections::HashMap};
 
+use serde::Serialize;
+
 /// "default" (no explicit namespace in the diagram)
 pub const DEFAULT_NAMESPACE: &str = "";
 
 type Sym<'a> = Cow<'a, str>;
 type OptSym<'a> = Option<Sym<'a>>;
 
+/// Where something was found in the source, as byte offsets with `end` exclusive, so
+/// `&source[span.start..span.end]` is the text it was parsed from. Only recorded when parsing
+/// with [`ParseOptions::spans`](crate::parserv2::ParseOptions::spans).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The same span `by` bytes further into the source
+    pub fn shift(self, by: usize) -> Span {
+        Span {
+            start: self.start + by,
+            end: self.end + by,
+        }
+    }
+}
+
+/// Keyword the diagram was declared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum DiagramHeader {
+    #[default]
+    ClassDiagram, // classDiagram
+    ClassDiagramV2, // classDiagram-v2
+}
+
 /// Direction of the diagram layout
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Direction {
     TopBottom, // TB or TD
     BottomTop, // BT
@@ -16,15 +48,24 @@ pub enum Direction {
 }
 
 /// Type annotation notation style
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum TypeNotation {
     Prefix,  // Type Name (e.g., "int x")
     Postfix, // Name: Type (e.g., "x: int")
     None,    // No type specified
 }
 
+impl TypeNotation {
+    /// `true` when no type was written in the diagram
+    pub fn is_none(self) -> bool {
+        self == TypeNotation::None
+    }
+}
+
 /// Public/Private/… like in Mermaid (# + ~ - or empty)
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Visibility {
     Public,    // +
     Private,   // -
@@ -33,6 +74,13 @@ pub enum Visibility {
     Unspecified,
 }
 
+impl Visibility {
+    /// `false` only for [`Visibility::Unspecified`], i.e. no symbol was written
+    pub fn is_specified(self) -> bool {
+        self != Visibility::Unspecified
+    }
+}
+
 impl From<char> for Visibility {
     fn from(c: char) -> Self {
         match c {
@@ -46,15 +94,19 @@ impl From<char> for Visibility {
 }
 
 /// A single parameter in a method signature
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Parameter<'source> {
     pub name: Sym<'source>,
     pub data_type: OptSym<'source>, // `None` if omitted in the diagram
     pub type_notation: TypeNotation, // Prefix, Postfix, or None
+    /// The default value as written, `5` or `"a"` (quotes included) for `x: int = 5`
+    pub default_value: OptSym<'source>,
 }
 
 /// A member inside a class box
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Member<'source> {
     /// `+fieldName: Type`
     Attribute(Attribute<'source>),
@@ -64,17 +116,20 @@ pub enum Member<'source> {
 }
 
 /// Data that only an **attribute** has
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Attribute<'source> {
     pub visibility: Visibility,
     pub name: Sym<'source>,
     pub data_type: OptSym<'source>,
     pub is_static: bool,             // "$" in Mermaid
     pub type_notation: TypeNotation, // Prefix, Postfix, or None
+    pub span: Option<Span>,
 }
 
 /// Data that only a **method** has
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Method<'source> {
     pub visibility: Visibility,
     pub name: Sym<'source>,
@@ -83,18 +138,37 @@ pub struct Method<'source> {
     pub is_static: bool,                    // "$" in Mermaid
     pub is_abstract: bool,                  // "*" in Mermaid
     pub return_type_notation: TypeNotation, // Prefix, Postfix, or None
+    pub span: Option<Span>,
+}
+
+/// What a `<<annotation>>` on a class says, with the ones Mermaid documents spelled out
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum AnnotationKind<'source> {
+    Interface,   // <<interface>>
+    Abstract,    // <<abstract>>
+    Enumeration, // <<enumeration>>
+    Service,     // <<service>>
+    Other(Sym<'source>),
 }
 
 /// A single class or interface in the diagram
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Class<'source> {
-    pub name: Sym<'source>,            // Fully-qualified (incl. namespace)
-    pub annotation: OptSym<'source>,   // <<interface>>, <<service>> …
+    pub name: Sym<'source>,         // Fully-qualified (incl. namespace)
+    pub generic: OptSym<'source>,   // `T` from `class Square~T~`
+    pub label: OptSym<'source>,     // display text from `class Name["Label"]`
+    pub css_class: OptSym<'source>, // `important` from `class Name:::important`
+    pub annotations: Vec<AnnotationKind<'source>>, // <<interface>>, <<service>> …
     pub members: Vec<Member<'source>>, // <── was Vec<ClassMember>
+    pub order: usize,               // declaration order across all namespaces
+    pub span: Option<Span>,         // the `class` statement, `None` if never declared with one
 }
 
 /// Mermaid’s five relation arrow-heads
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum RelationKind {
     Inheritance, // <|--
     Composition, // *--
@@ -107,40 +181,1312 @@ pub enum RelationKind {
     Lollipop,    // --()
 }
 
+/// Whether a relation is drawn with a solid or a dotted line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum LineStyle {
+    #[default]
+    Solid, // --
+    Dotted, // ..
+}
+
+impl LineStyle {
+    /// The line style an arrow is drawn with
+    pub fn of_arrow(arrow: &str) -> Self {
+        if arrow.contains('.') {
+            LineStyle::Dotted
+        } else {
+            LineStyle::Solid
+        }
+    }
+}
+
+impl RelationKind {
+    /// The arrow for this kind pointing from tail to head, drawn with `line`. The line wins over
+    /// the kind, so `Dependency` with a solid line is written `-->` like `Association`.
+    pub fn forward_arrow(self, line: LineStyle) -> &'static str {
+        use LineStyle::{Dotted, Solid};
+        use RelationKind::*;
+
+        match (self, line) {
+            (Inheritance | Realization, Solid) => "--|>",
+            (Inheritance | Realization, Dotted) => "..|>",
+            (Composition, Solid) => "--*",
+            (Composition, Dotted) => "..*",
+            (Aggregation, Solid) => "--o",
+            (Aggregation, Dotted) => "..o",
+            (Association | Dependency, Solid) => "-->",
+            (Association | Dependency, Dotted) => "..>",
+            (SolidLink | DashLink, Solid) => "--",
+            (SolidLink | DashLink, Dotted) => "..",
+            (Lollipop, Solid) => "--()",
+            (Lollipop, Dotted) => "..()",
+        }
+    }
+
+    /// The same arrow as [`RelationKind::forward_arrow`], pointing from head to tail instead
+    /// (`<|--`). Plain links read the same both ways.
+    pub fn backward_arrow(self, line: LineStyle) -> &'static str {
+        use LineStyle::{Dotted, Solid};
+        use RelationKind::*;
+
+        match (self, line) {
+            (Inheritance | Realization, Solid) => "<|--",
+            (Inheritance | Realization, Dotted) => "<|..",
+            (Composition, Solid) => "*--",
+            (Composition, Dotted) => "*..",
+            (Aggregation, Solid) => "o--",
+            (Aggregation, Dotted) => "o..",
+            (Association | Dependency, Solid) => "<--",
+            (Association | Dependency, Dotted) => "<..",
+            (SolidLink | DashLink, Solid) => "--",
+            (SolidLink | DashLink, Dotted) => "..",
+            (Lollipop, Solid) => "()--",
+            (Lollipop, Dotted) => "()..",
+        }
+    }
+
+    /// Look up an arrow as written in a diagram. Returns the kind and whether the arrow points
+    /// backward, from right to left (`<|--`). Dotted arrows map to their dotted kind where one
+    /// exists (`..|>` is `Realization`, `..>` is `Dependency`); use [`LineStyle::of_arrow`] for
+    /// the line itself. Lines may be drawn longer than two characters (`---->`).
+    pub fn from_arrow(arrow: &str) -> Option<(Self, bool)> {
+        use RelationKind::*;
+
+        let collapsed;
+        let arrow = if arrow.contains("---") || arrow.contains("...") {
+            collapsed = collapse_line(arrow);
+            collapsed.as_str()
+        } else {
+            arrow
+        };
+
+        let found = match arrow {
+            "--|>" => (Inheritance, false),
+            "<|--" => (Inheritance, true),
+            "..|>" => (Realization, false),
+            "<|.." => (Realization, true),
+            "--*" | "..*" => (Composition, false),
+            "*--" | "*.." => (Composition, true),
+            "--o" | "..o" => (Aggregation, false),
+            "o--" | "o.." => (Aggregation, true),
+            "-->" => (Association, false),
+            "<--" => (Association, true),
+            "..>" => (Dependency, false),
+            "<.." => (Dependency, true),
+            "--" => (SolidLink, false),
+            ".." => (DashLink, false),
+            "--()" | "..()" => (Lollipop, false),
+            "()--" | "().." => (Lollipop, true),
+            _ => return None,
+        };
+        Some(found)
+    }
+}
+
+/// Shorten every run of `-` or `.` in an arrow to two characters
+fn collapse_line(arrow: &str) -> String {
+    let mut collapsed = String::with_capacity(arrow.len());
+    for c in arrow.chars() {
+        let run = (c == '-' || c == '.') && collapsed.as_bytes().ends_with(&[c as u8; 2]);
+        if !run {
+            collapsed.push(c);
+        }
+    }
+    collapsed
+}
+
 /// Edge between two classes
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Relation<'source> {
     /// The class name which the tail comes FROM.
     pub tail: Sym<'source>, // fully-qualified class names
     /// The class name which the head is attached TO
     pub head: Sym<'source>,
     pub kind: RelationKind,
+    pub line: LineStyle,
     pub cardinality_tail: OptSym<'source>, // e.g., "1", "*", "1..*"
     pub cardinality_head: OptSym<'source>, // e.g., "1", "*", "1..*"
     pub label: OptSym<'source>,            // relationship label text
+    /// The arrow exactly as written, kept only when it isn't a standard spelling (`---->`) so
+    /// the serializer can reproduce it. `None` for ordinary arrows.
+    pub raw_operator: OptSym<'source>,
+    /// The arrow pointed backward (`B <|-- A`) and the parser swapped the ends so that `tail` and
+    /// `head` follow the arrow. Lets the serializer write the relation the way it was written.
+    pub reversed: bool,
+    pub span: Option<Span>,
+}
+
+/// Where a note sits relative to its class, for `note left of ClassName "text"` and friends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum NotePlacement {
+    LeftOf,  // left of
+    RightOf, // right of
+    Over,    // over
 }
 
 /// A note in the diagram - either general or attached to a specific class
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Note<'source> {
-    pub text: Sym<'source>,            // the note content
+    pub text: Sym<'source>,               // the note content
     pub target_class: OptSym<'source>, // None for general notes, Some(class) for "note for ClassName"
+    pub placement: Option<NotePlacement>, // None for "note for ClassName" and general notes
+    pub span: Option<Span>,
+}
+
+/// A `style ClassName fill:#f9f,stroke:#333` statement
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct StyleDirective<'source> {
+    pub target: Sym<'source>,
+    pub declarations: Vec<(Sym<'source>, Sym<'source>)>, // CSS property ➜ value
 }
 
 /// Recursive namespace tree
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Namespace<'source> {
     pub name: Sym<'source>,
     pub classes: HashMap<Sym<'source>, Class<'source>>, // name ➜ class
     pub children: HashMap<Sym<'source>, Namespace<'source>>, // nested namespaces
+    pub direction: Option<Direction>,                   // `direction LR` inside the namespace block
 }
 
 /// Whole diagram
-#[derive(Debug, Default)]
+///
+/// Two diagrams compare equal when they hold the same data. Classes and namespaces live in maps,
+/// so the order they were declared in only matters through [`Class::order`], while relations,
+/// notes, styles and directives have to appear in the same order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Diagram<'source> {
+    pub header: DiagramHeader,
     pub namespaces: HashMap<Sym<'source>, Namespace<'source>>,
     pub relations: Vec<Relation<'source>>,
     pub notes: Vec<Note<'source>>,
+    pub styles: Vec<StyleDirective<'source>>,
     pub direction: Option<Direction>,
+    #[cfg_attr(feature = "schema", schemars(with = "Option<crate::schema::AnyValue>"))]
     pub yaml: Option<serde_yml::Value>,
+    /// The frontmatter text between the `---` lines as it was written, so a formatter can leave
+    /// it untouched. `None` for diagrams that weren't parsed from text.
+    pub raw_yaml: OptSym<'source>,
+    #[cfg_attr(feature = "schema", schemars(with = "Vec<crate::schema::AnyValue>"))]
+    pub directives: Vec<serde_yml::Value>, // %%{init: {...}}%%
+}
+
+/// Counts returned by [`Diagram::stats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DiagramStats {
+    pub classes: usize,
+    pub attributes: usize,
+    pub methods: usize,
+    pub relations: usize,
+    pub relation_kinds: HashMap<RelationKind, usize>,
+    pub notes: usize,
+    pub namespaces: usize, // named ones, nested namespaces included
+}
+
+/// The last `::` separated segment of a qualified name
+fn local_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// `Map~K, List~V~~` split into `Map` and its generic arguments `["K", "List~V~"]`. A `~` that is
+/// followed by a closing `~`, a `,` or the end of the type closes a nested argument list, any
+/// other one opens one.
+fn split_generic(ty: &str) -> (&str, Vec<&str>) {
+    let Some((base, rest)) = ty.split_once('~') else {
+        return (ty, Vec::new());
+    };
+    let inner = rest.strip_suffix('~').unwrap_or(rest);
+
+    let mut args = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '~' if matches!(inner[i + 1..].chars().next(), None | Some('~' | ',')) => {
+                depth = depth.saturating_sub(1);
+            }
+            '~' => depth += 1,
+            ',' if depth == 0 => {
+                args.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(inner[start..].trim());
+    args.retain(|arg| !arg.is_empty());
+    (base.trim(), args)
+}
+
+/// A qualified name split into its namespace path, `None` for the default namespace, and the
+/// local name
+fn split_qualified(name: &str) -> (Option<&str>, &str) {
+    match name.rsplit_once("::") {
+        Some((path, local)) => (Some(path), local),
+        None => (None, name),
+    }
+}
+
+/// Detach a symbol from the source it borrows from
+fn owned(sym: Sym<'_>) -> Sym<'static> {
+    Cow::Owned(sym.into_owned())
+}
+
+impl Parameter<'_> {
+    /// The data type without its generic arguments, `List` for `List~int~`
+    pub fn base_type(&self) -> Option<&str> {
+        self.data_type.as_deref().map(|ty| split_generic(ty).0)
+    }
+
+    /// The generic arguments of the data type, `["K", "V"]` for `Map~K, V~`
+    pub fn generic_args(&self) -> Vec<&str> {
+        self.data_type
+            .as_deref()
+            .map_or_else(Vec::new, |ty| split_generic(ty).1)
+    }
+
+    pub fn into_owned(self) -> Parameter<'static> {
+        Parameter {
+            name: owned(self.name),
+            data_type: self.data_type.map(owned),
+            type_notation: self.type_notation,
+            default_value: self.default_value.map(owned),
+        }
+    }
+}
+
+impl Member<'_> {
+    pub fn name(&self) -> &str {
+        match self {
+            Member::Attribute(attr) => &attr.name,
+            Member::Method(method) => &method.name,
+        }
+    }
+
+    pub fn visibility(&self) -> Visibility {
+        match self {
+            Member::Attribute(attr) => attr.visibility,
+            Member::Method(method) => method.visibility,
+        }
+    }
+
+    /// `$` in Mermaid
+    pub fn is_static(&self) -> bool {
+        match self {
+            Member::Attribute(attr) => attr.is_static,
+            Member::Method(method) => method.is_static,
+        }
+    }
+
+    /// `*` in Mermaid, only methods can be abstract
+    pub fn is_abstract(&self) -> bool {
+        matches!(self, Member::Method(method) if method.is_abstract)
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Member::Attribute(attr) => attr.span,
+            Member::Method(method) => method.span,
+        }
+    }
+
+    pub fn into_owned(self) -> Member<'static> {
+        match self {
+            Member::Attribute(attr) => Member::Attribute(attr.into_owned()),
+            Member::Method(method) => Member::Method(method.into_owned()),
+        }
+    }
+}
+
+impl Attribute<'_> {
+    /// The data type without its generic arguments, `List` for `List~int~`
+    pub fn base_type(&self) -> Option<&str> {
+        self.data_type.as_deref().map(|ty| split_generic(ty).0)
+    }
+
+    /// The generic arguments of the data type, `["K", "V"]` for `Map~K, V~`
+    pub fn generic_args(&self) -> Vec<&str> {
+        self.data_type
+            .as_deref()
+            .map_or_else(Vec::new, |ty| split_generic(ty).1)
+    }
+
+    pub fn into_owned(self) -> Attribute<'static> {
+        Attribute {
+            visibility: self.visibility,
+            name: owned(self.name),
+            data_type: self.data_type.map(owned),
+            is_static: self.is_static,
+            type_notation: self.type_notation,
+            span: self.span,
+        }
+    }
+}
+
+impl Method<'_> {
+    /// The return type without its generic arguments, `List` for `List~int~`
+    pub fn base_type(&self) -> Option<&str> {
+        self.return_type.as_deref().map(|ty| split_generic(ty).0)
+    }
+
+    /// The generic arguments of the return type, `["K", "V"]` for `Map~K, V~`
+    pub fn generic_args(&self) -> Vec<&str> {
+        self.return_type
+            .as_deref()
+            .map_or_else(Vec::new, |ty| split_generic(ty).1)
+    }
+
+    pub fn into_owned(self) -> Method<'static> {
+        Method {
+            visibility: self.visibility,
+            name: owned(self.name),
+            parameters: self
+                .parameters
+                .into_iter()
+                .map(Parameter::into_owned)
+                .collect(),
+            return_type: self.return_type.map(owned),
+            is_static: self.is_static,
+            is_abstract: self.is_abstract,
+            return_type_notation: self.return_type_notation,
+            span: self.span,
+        }
+    }
+}
+
+impl AnnotationKind<'_> {
+    /// The text between the angle brackets
+    pub fn as_str(&self) -> &str {
+        match self {
+            AnnotationKind::Interface => "interface",
+            AnnotationKind::Abstract => "abstract",
+            AnnotationKind::Enumeration => "enumeration",
+            AnnotationKind::Service => "service",
+            AnnotationKind::Other(other) => other,
+        }
+    }
+
+    pub fn into_owned(self) -> AnnotationKind<'static> {
+        match self {
+            AnnotationKind::Interface => AnnotationKind::Interface,
+            AnnotationKind::Abstract => AnnotationKind::Abstract,
+            AnnotationKind::Enumeration => AnnotationKind::Enumeration,
+            AnnotationKind::Service => AnnotationKind::Service,
+            AnnotationKind::Other(other) => AnnotationKind::Other(owned(other)),
+        }
+    }
+}
+
+/// Surrounding whitespace is ignored and the well-known annotations match in any ASCII case
+impl<'source> From<Sym<'source>> for AnnotationKind<'source> {
+    fn from(annotation: Sym<'source>) -> Self {
+        let trimmed = annotation.trim();
+        for known in [
+            AnnotationKind::Interface,
+            AnnotationKind::Abstract,
+            AnnotationKind::Enumeration,
+            AnnotationKind::Service,
+        ] {
+            if trimmed.eq_ignore_ascii_case(known.as_str()) {
+                return known;
+            }
+        }
+        match annotation {
+            Cow::Borrowed(annotation) => AnnotationKind::Other(Cow::Borrowed(annotation.trim())),
+            Cow::Owned(annotation) => {
+                AnnotationKind::Other(Cow::Owned(annotation.trim().to_owned()))
+            }
+        }
+    }
+}
+
+impl<'source> From<&'source str> for AnnotationKind<'source> {
+    fn from(annotation: &'source str) -> Self {
+        AnnotationKind::from(Cow::Borrowed(annotation))
+    }
+}
+
+impl<'source> Class<'source> {
+    /// Whether the class is annotated with `<<annotation>>`, ignoring ASCII case
+    pub fn has_annotation(&self, annotation: &str) -> bool {
+        self.annotations
+            .iter()
+            .any(|a| a.as_str().eq_ignore_ascii_case(annotation))
+    }
+
+    /// Add `annotation` unless the class already has it
+    pub fn annotate(&mut self, annotation: AnnotationKind<'source>) {
+        if !self.annotations.contains(&annotation) {
+            self.annotations.push(annotation);
+        }
+    }
+
+    /// `<<interface>>`
+    pub fn is_interface(&self) -> bool {
+        self.annotations.contains(&AnnotationKind::Interface)
+    }
+
+    /// `<<enumeration>>`, or the shorter `<<enum>>`
+    pub fn is_enum(&self) -> bool {
+        self.annotations.contains(&AnnotationKind::Enumeration) || self.has_annotation("enum")
+    }
+
+    /// `<<service>>`
+    pub fn is_service(&self) -> bool {
+        self.annotations.contains(&AnnotationKind::Service)
+    }
+
+    pub fn into_owned(self) -> Class<'static> {
+        Class {
+            name: owned(self.name),
+            generic: self.generic.map(owned),
+            label: self.label.map(owned),
+            css_class: self.css_class.map(owned),
+            annotations: self
+                .annotations
+                .into_iter()
+                .map(AnnotationKind::into_owned)
+                .collect(),
+            members: self.members.into_iter().map(Member::into_owned).collect(),
+            order: self.order,
+            span: self.span,
+        }
+    }
+}
+
+impl Relation<'_> {
+    /// `true` when the relation starts and ends at the same class (`Node --> Node`)
+    pub fn is_self_loop(&self) -> bool {
+        self.tail == self.head
+    }
+
+    /// The standard arrow for this relation's kind and line, pointing from tail to head. Unlike
+    /// the serializer this ignores `raw_operator` and `reversed`.
+    pub fn arrow(&self) -> &'static str {
+        self.kind.forward_arrow(self.line)
+    }
+
+    pub fn into_owned(self) -> Relation<'static> {
+        Relation {
+            tail: owned(self.tail),
+            head: owned(self.head),
+            kind: self.kind,
+            line: self.line,
+            cardinality_tail: self.cardinality_tail.map(owned),
+            cardinality_head: self.cardinality_head.map(owned),
+            label: self.label.map(owned),
+            raw_operator: self.raw_operator.map(owned),
+            reversed: self.reversed,
+            span: self.span,
+        }
+    }
+}
+
+impl Note<'_> {
+    pub fn into_owned(self) -> Note<'static> {
+        Note {
+            text: owned(self.text),
+            target_class: self.target_class.map(owned),
+            placement: self.placement,
+            span: self.span,
+        }
+    }
+}
+
+impl StyleDirective<'_> {
+    pub fn into_owned(self) -> StyleDirective<'static> {
+        StyleDirective {
+            target: owned(self.target),
+            declarations: self
+                .declarations
+                .into_iter()
+                .map(|(property, value)| (owned(property), owned(value)))
+                .collect(),
+        }
+    }
+}
+
+impl<'source> Namespace<'source> {
+    /// Add a class keyed by its local name, the part after the last `::`. A class with the same
+    /// local name is replaced.
+    pub fn add_class(&mut self, class: Class<'source>) {
+        let key = match &class.name {
+            Cow::Borrowed(name) => Cow::Borrowed(local_name(name)),
+            Cow::Owned(name) => Cow::Owned(local_name(name).to_owned()),
+        };
+        self.classes.insert(key, class);
+    }
+
+    /// Nest `child` under this namespace, replacing any child with the same name. It is written
+    /// out as a `namespace` block inside this one.
+    pub fn add_child(&mut self, child: Namespace<'source>) {
+        self.children.insert(child.name.clone(), child);
+    }
+
+    /// The classes of this namespace and of every namespace nested in it
+    pub(crate) fn all_classes_mut(&mut self) -> Vec<&mut Class<'source>> {
+        let mut classes = Vec::new();
+        let mut stack = vec![self];
+        while let Some(ns) = stack.pop() {
+            classes.extend(ns.classes.values_mut());
+            stack.extend(ns.children.values_mut());
+        }
+        classes
+    }
+
+    pub fn into_owned(self) -> Namespace<'static> {
+        Namespace {
+            name: owned(self.name),
+            classes: self
+                .classes
+                .into_iter()
+                .map(|(name, class)| (owned(name), class.into_owned()))
+                .collect(),
+            children: self
+                .children
+                .into_iter()
+                .map(|(name, child)| (owned(name), child.into_owned()))
+                .collect(),
+            direction: self.direction,
+        }
+    }
+}
+
+impl<'source> Diagram<'source> {
+    /// The `title` from the frontmatter. `None` when there is no frontmatter, it isn't a mapping
+    /// (a bare scalar or a list is valid YAML too), or the title isn't a string.
+    pub fn title(&self) -> Option<&str> {
+        self.yaml.as_ref()?.as_mapping()?.get("title")?.as_str()
+    }
+
+    /// Number of classes across all namespaces
+    pub fn class_count(&self) -> usize {
+        self.all_namespaces().map(|ns| ns.classes.len()).sum()
+    }
+
+    pub fn relation_count(&self) -> usize {
+        self.relations.len()
+    }
+
+    /// Count what the diagram holds
+    pub fn stats(&self) -> DiagramStats {
+        let mut stats = DiagramStats {
+            relations: self.relations.len(),
+            notes: self.notes.len(),
+            ..Default::default()
+        };
+
+        for ns in self.all_namespaces() {
+            if ns.name != DEFAULT_NAMESPACE {
+                stats.namespaces += 1;
+            }
+            for class in ns.classes.values() {
+                stats.classes += 1;
+                for member in &class.members {
+                    match member {
+                        Member::Attribute(_) => stats.attributes += 1,
+                        Member::Method(_) => stats.methods += 1,
+                    }
+                }
+            }
+        }
+
+        for relation in &self.relations {
+            *stats.relation_kinds.entry(relation.kind).or_default() += 1;
+        }
+        stats
+    }
+
+    /// Every namespace, nested ones included
+    fn all_namespaces(&self) -> impl Iterator<Item = &Namespace<'source>> {
+        let mut stack: Vec<_> = self.namespaces.values().collect();
+        std::iter::from_fn(move || {
+            let ns = stack.pop()?;
+            stack.extend(ns.children.values());
+            Some(ns)
+        })
+    }
+
+    /// Look up a class the way relations and notes name it. A qualified name (`Animals::Dog`,
+    /// `Outer::Inner::Leaf`) is looked up in that namespace, a plain name in the default one.
+    pub fn find_class(&self, name: &str) -> Option<&Class<'source>> {
+        let Some((path, local)) = name.rsplit_once("::") else {
+            return self.namespaces.get(DEFAULT_NAMESPACE)?.classes.get(name);
+        };
+
+        let mut segments = path.split("::");
+        let mut ns = self.namespaces.get(segments.next()?)?;
+        for segment in segments {
+            ns = ns.children.get(segment)?;
+        }
+        ns.classes.get(local)
+    }
+
+    /// Like [`Diagram::find_class`], but the class can be modified
+    pub fn find_class_mut(&mut self, name: &str) -> Option<&mut Class<'source>> {
+        let Some((path, local)) = name.rsplit_once("::") else {
+            return self
+                .namespaces
+                .get_mut(DEFAULT_NAMESPACE)?
+                .classes
+                .get_mut(name);
+        };
+
+        let mut segments = path.split("::");
+        let mut ns = self.namespaces.get_mut(segments.next()?)?;
+        for segment in segments {
+            ns = ns.children.get_mut(segment)?;
+        }
+        ns.classes.get_mut(local)
+    }
+
+    /// Relation endpoints that [`Diagram::find_class`] can't match to a declared class, in the
+    /// order they appear. Mermaid declares a class the first time a relation mentions it, so an
+    /// unknown plain name is often fine, while an unknown qualified name is most likely a typo.
+    pub fn unresolved_endpoints(&self) -> Vec<&str> {
+        self.relations
+            .iter()
+            .flat_map(|rel| [rel.tail.as_ref(), rel.head.as_ref()])
+            .filter(|name| self.find_class(name).is_none())
+            .collect()
+    }
+
+    /// Rename the class `old` to `new` along with every relation end, note and style that refers
+    /// to it, returning whether anything changed. Both names are qualified like in
+    /// [`Diagram::find_class`], so a different namespace path moves the class into that
+    /// namespace. Nothing is changed if `new` is already a class or its namespace doesn't exist.
+    pub fn rename_class(&mut self, old: &str, new: &str) -> bool {
+        if old == new {
+            return false;
+        }
+
+        let mut changed = false;
+        if self.find_class(old).is_some() {
+            let (old_path, old_local) = split_qualified(old);
+            let (new_path, new_local) = split_qualified(new);
+            if self.find_class(new).is_some()
+                || (new_path.is_some() && self.namespace_at_mut(new_path).is_none())
+            {
+                return false;
+            }
+
+            let old_namespace = self.namespace_at_mut(old_path).expect("class was found");
+            let mut class = old_namespace
+                .classes
+                .remove(old_local)
+                .expect("class was found");
+            class.name = Cow::Owned(new_local.to_owned());
+            self.namespace_at_mut(new_path)
+                .expect("namespace was checked")
+                .classes
+                .insert(class.name.clone(), class);
+            changed = true;
+        }
+
+        let mut rename = |name: &mut Sym<'source>| {
+            if name == old {
+                *name = Cow::Owned(new.to_owned());
+                changed = true;
+            }
+        };
+        for relation in &mut self.relations {
+            rename(&mut relation.tail);
+            rename(&mut relation.head);
+        }
+        for note in &mut self.notes {
+            if let Some(target) = &mut note.target_class {
+                rename(target);
+            }
+        }
+        for style in &mut self.styles {
+            rename(&mut style.target);
+        }
+        changed
+    }
+
+    /// The namespace at `path` (`A::B` for `A::B::Class`), or the default namespace for `None`,
+    /// which is created if needed
+    fn namespace_at_mut(&mut self, path: Option<&str>) -> Option<&mut Namespace<'source>> {
+        let Some(path) = path else {
+            return Some(
+                self.namespaces
+                    .entry(Cow::Borrowed(DEFAULT_NAMESPACE))
+                    .or_default(),
+            );
+        };
+
+        let mut segments = path.split("::");
+        let mut ns = self.namespaces.get_mut(segments.next()?)?;
+        for segment in segments {
+            ns = ns.children.get_mut(segment)?;
+        }
+        Some(ns)
+    }
+
+    /// Relations whose tail is `class`. Names are matched exactly as the relation stores them,
+    /// so a namespaced class is looked up by its qualified name (`Ns::Class`).
+    pub fn relations_from<'a>(
+        &'a self,
+        class: &'a str,
+    ) -> impl Iterator<Item = &'a Relation<'source>> + 'a {
+        self.relations.iter().filter(move |rel| rel.tail == class)
+    }
+
+    /// Relations whose head is `class`, matched the same way as [`Diagram::relations_from`]
+    pub fn relations_to<'a>(
+        &'a self,
+        class: &'a str,
+    ) -> impl Iterator<Item = &'a Relation<'source>> + 'a {
+        self.relations.iter().filter(move |rel| rel.head == class)
+    }
+
+    /// Relations from `tail` to `head`, in that order, matched like [`Diagram::relations_from`]
+    pub fn find_relations<'a>(
+        &'a self,
+        tail: &'a str,
+        head: &'a str,
+    ) -> impl Iterator<Item = &'a Relation<'source>> + 'a {
+        self.relations_from(tail)
+            .filter(move |rel| rel.head == head)
+    }
+
+    /// Convert every borrowed string into an owned one so the diagram outlives its source
+    pub fn into_owned(self) -> Diagram<'static> {
+        Diagram {
+            header: self.header,
+            namespaces: self
+                .namespaces
+                .into_iter()
+                .map(|(name, ns)| (owned(name), ns.into_owned()))
+                .collect(),
+            relations: self
+                .relations
+                .into_iter()
+                .map(Relation::into_owned)
+                .collect(),
+            notes: self.notes.into_iter().map(Note::into_owned).collect(),
+            styles: self
+                .styles
+                .into_iter()
+                .map(StyleDirective::into_owned)
+                .collect(),
+            direction: self.direction,
+            yaml: self.yaml,
+            raw_yaml: self.raw_yaml.map(owned),
+            directives: self.directives,
+        }
+    }
+
+    /// Like [`Diagram::into_owned`] but leaves `self` untouched
+    pub fn to_owned(&self) -> Diagram<'static> {
+        self.clone().into_owned()
+    }
+}
+
+/// Parse with [`parse_mermaid`](crate::parserv2::parse_mermaid). The result can't borrow from
+/// the string, so it is always owned.
+///
+/// ```
+/// use mermaid_parser::types::Diagram;
+///
+/// let diagram: Diagram = "classDiagram\nclass A".parse()?;
+/// assert!(diagram.find_class("A").is_some());
+///
+/// let diagram = "classDiagram\nclass A".parse::<Diagram>()?;
+/// assert_eq!(diagram.class_count(), 1);
+/// # Ok::<(), mermaid_parser::parserv2::MermaidParseError>(())
+/// ```
+impl std::str::FromStr for Diagram<'static> {
+    type Err = crate::parserv2::MermaidParseError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        crate::parserv2::parse_mermaid(source).map(Diagram::into_owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parserv2::parse_mermaid;
+
+    #[test]
+    fn test_diagram_find_class() {
+        let source = "classDiagram\nclass Person\nnamespace Animals {\n  class Dog\n}\nAnimals::Dog --> Person\nAnimals::Unicorn --> Vehicles::Car\nPerson --> Pet\n";
+        let diagram = parse_mermaid(source).expect("Failed to parse");
+        assert_eq!(diagram.relations[1].tail, "Animals::Unicorn");
+
+        assert_eq!(diagram.find_class("Person").unwrap().name, "Person");
+        assert_eq!(diagram.find_class("Animals::Dog").unwrap().name, "Dog");
+        assert!(diagram.find_class("Dog").is_none());
+        assert!(diagram.find_class("Animals::Cat").is_none());
+        assert!(diagram.find_class("Vehicles::Car").is_none());
+
+        assert_eq!(
+            diagram.unresolved_endpoints(),
+            ["Animals::Unicorn", "Vehicles::Car", "Pet"]
+        );
+
+        // Nested namespaces are followed segment by segment
+        let mut diagram = diagram;
+        let mut inner = Namespace {
+            name: "Inner".into(),
+            ..Default::default()
+        };
+        inner.add_class(Class {
+            name: "Outer::Inner::Leaf".into(),
+            generic: None,
+            label: None,
+            css_class: None,
+            annotations: Vec::new(),
+            members: Vec::new(),
+            order: 0,
+            span: None,
+        });
+        let mut outer = Namespace {
+            name: "Outer".into(),
+            ..Default::default()
+        };
+        outer.add_child(inner);
+        diagram.namespaces.insert("Outer".into(), outer);
+        assert!(diagram.find_class("Outer::Inner::Leaf").is_some());
+        assert!(diagram.find_class("Outer::Leaf").is_none());
+    }
+
+    #[test]
+    fn test_diagram_stats() {
+        let source = r#"classDiagram
+direction RL
+class `Animal Class!`
+class Vehicle
+`Animal Class!` : +int age
+`Animal Class!` : +name: String
+`Animal Class!` : +move(int distance) void
+Vehicle : +speed: int
+Vehicle : +drive(a: int, b: String) int
+`Animal Class!` "1" --> "*" Vehicle : owns
+Vehicle <|-- Car
+Vehicle <|-- Bike
+namespace Shop {
+  class Order
+  class Item
+}
+note "This is a test diagram"
+note for Vehicle "Vehicles are fast"
+"#;
+        let diagram = parse_mermaid(source).expect("Failed to parse");
+        let stats = diagram.stats();
+
+        assert_eq!(stats.classes, 4);
+        assert_eq!(stats.attributes, 3);
+        assert_eq!(stats.methods, 2);
+        assert_eq!(stats.relations, 3);
+        assert_eq!(
+            stats.relation_kinds,
+            HashMap::from([
+                (RelationKind::Association, 1),
+                (RelationKind::Inheritance, 2)
+            ])
+        );
+        assert_eq!(stats.notes, 2);
+        assert_eq!(stats.namespaces, 1);
+        assert_eq!(diagram.class_count(), 4);
+        assert_eq!(diagram.relation_count(), 3);
+
+        // Nested namespaces are counted too
+        let mut diagram = diagram;
+        let mut outer = Namespace {
+            name: "Outer".into(),
+            ..Default::default()
+        };
+        let mut inner = Namespace {
+            name: "Inner".into(),
+            ..Default::default()
+        };
+        inner.add_class(Class {
+            name: "Outer::Inner::Leaf".into(),
+            generic: None,
+            label: None,
+            css_class: None,
+            annotations: Vec::new(),
+            members: Vec::new(),
+            order: 0,
+            span: None,
+        });
+        outer.add_child(inner);
+        diagram.namespaces.insert("Outer".into(), outer);
+        assert_eq!(diagram.stats().namespaces, 3);
+        assert_eq!(diagram.class_count(), 5);
+
+        assert_eq!(Diagram::default().stats(), DiagramStats::default());
+    }
+
+    #[test]
+    fn test_member_accessors() {
+        let source =
+            "classDiagram\nclass A {\n  -$count: int\n  +*area() double\n  #$reset()\n  name\n}\n";
+        let diagram = parse_mermaid(source).expect("Failed to parse");
+        let members = &diagram.namespaces[DEFAULT_NAMESPACE].classes["A"].members;
+
+        let summary: Vec<_> = members
+            .iter()
+            .map(|member| {
+                (
+                    member.name(),
+                    member.visibility(),
+                    member.is_static(),
+                    member.is_abstract(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("count", Visibility::Private, true, false),
+                ("area", Visibility::Public, false, true),
+                ("reset", Visibility::Protected, true, false),
+                ("name", Visibility::Unspecified, false, false),
+            ]
+        );
+        assert!(matches!(members[0], Member::Attribute(_)));
+        assert!(matches!(members[1], Member::Method(_)));
+    }
+
+    #[test]
+    fn test_member_generic_types() {
+        let source = "classDiagram\nclass A {\n  +int count\n  +List~int~ items\n  +lookup(Map~K, List~V~~ index) Map~K, V~\n  +clear()\n}\n";
+        let diagram = parse_mermaid(source).expect("Failed to parse");
+        let members = &diagram.namespaces[DEFAULT_NAMESPACE].classes["A"].members;
+
+        let Member::Attribute(count) = &members[0] else {
+            panic!("count should be an attribute");
+        };
+        assert_eq!(count.base_type(), Some("int"));
+        assert!(count.generic_args().is_empty());
+
+        let Member::Attribute(items) = &members[1] else {
+            panic!("items should be an attribute");
+        };
+        assert_eq!(items.base_type(), Some("List"));
+        assert_eq!(items.generic_args(), ["int"]);
+
+        let Member::Method(lookup) = &members[2] else {
+            panic!("lookup should be a method");
+        };
+        assert_eq!(lookup.base_type(), Some("Map"));
+        assert_eq!(lookup.generic_args(), ["K", "V"]);
+        assert_eq!(lookup.parameters[0].base_type(), Some("Map"));
+        assert_eq!(lookup.parameters[0].generic_args(), ["K", "List~V~"]);
+
+        let Member::Method(clear) = &members[3] else {
+            panic!("clear should be a method");
+        };
+        assert_eq!(clear.base_type(), None);
+        assert!(clear.generic_args().is_empty());
+    }
+
+    #[test]
+    fn test_namespace_add_class_and_child() {
+        let class = |name: &'static str| Class {
+            name: name.into(),
+            generic: None,
+            label: None,
+            css_class: None,
+            annotations: Vec::new(),
+            members: Vec::new(),
+            order: 0,
+            span: None,
+        };
+
+        let mut inner = Namespace {
+            name: "Inner".into(),
+            ..Default::default()
+        };
+        inner.add_class(class("Outer::Inner::Leaf"));
+
+        let mut outer = Namespace {
+            name: "Outer".into(),
+            ..Default::default()
+        };
+        outer.add_class(class("Outer::Root"));
+        outer.add_class(Class {
+            name: Cow::Owned("Outer::Other".to_owned()),
+            ..class("")
+        });
+        outer.add_child(inner);
+
+        assert!(outer.classes.contains_key("Root"));
+        assert!(outer.classes.contains_key("Other"));
+        assert!(outer.children["Inner"].classes.contains_key("Leaf"));
+
+        // The child is written as a block inside its parent and reads back as one
+        let mut diagram = Diagram::default();
+        diagram.namespaces.insert(outer.name.clone(), outer);
+        let serialized = crate::serializer::serialize_diagram(&diagram);
+        assert!(serialized.contains("namespace Outer {\n"));
+        assert!(serialized.contains("class Root\n"));
+        assert!(serialized.contains("class Other\n"));
+        assert!(
+            serialized.contains("namespace Inner {\nclass Leaf\n}\n"),
+            "{serialized}"
+        );
+
+        let parsed = parse_mermaid(&serialized).unwrap();
+        assert!(parsed.find_class("Outer::Inner::Leaf").is_some());
+        assert!(parsed.find_class("Outer::Root").is_some());
+    }
+
+    #[test]
+    fn test_visibility_is_specified() {
+        assert!(Visibility::Public.is_specified());
+        assert!(Visibility::Private.is_specified());
+        assert!(Visibility::Protected.is_specified());
+        assert!(Visibility::Package.is_specified());
+        assert!(!Visibility::Unspecified.is_specified());
+        assert!(!Visibility::from('x').is_specified());
+    }
+
+    #[test]
+    fn test_relation_kind_arrows() {
+        use RelationKind::*;
+
+        let arrows = [
+            ("--|>", "<|--", Inheritance, LineStyle::Solid),
+            ("..|>", "<|..", Realization, LineStyle::Dotted),
+            ("--*", "*--", Composition, LineStyle::Solid),
+            ("..*", "*..", Composition, LineStyle::Dotted),
+            ("--o", "o--", Aggregation, LineStyle::Solid),
+            ("..o", "o..", Aggregation, LineStyle::Dotted),
+            ("-->", "<--", Association, LineStyle::Solid),
+            ("..>", "<..", Dependency, LineStyle::Dotted),
+            ("--()", "()--", Lollipop, LineStyle::Solid),
+            ("..()", "()..", Lollipop, LineStyle::Dotted),
+        ];
+        for (forward, backward, kind, line) in arrows {
+            assert_eq!(RelationKind::from_arrow(forward), Some((kind, false)));
+            assert_eq!(RelationKind::from_arrow(backward), Some((kind, true)));
+            assert_eq!(LineStyle::of_arrow(forward), line);
+            assert_eq!(LineStyle::of_arrow(backward), line);
+            assert_eq!(kind.forward_arrow(line), forward);
+            assert_eq!(kind.backward_arrow(line), backward);
+        }
+
+        // Plain links have no direction
+        assert_eq!(RelationKind::from_arrow("--"), Some((SolidLink, false)));
+        assert_eq!(RelationKind::from_arrow(".."), Some((DashLink, false)));
+        assert_eq!(SolidLink.forward_arrow(LineStyle::Solid), "--");
+        assert_eq!(DashLink.forward_arrow(LineStyle::Dotted), "..");
+        assert_eq!(SolidLink.backward_arrow(LineStyle::Solid), "--");
+
+        // The line style decides between the solid and dotted spelling of a head
+        assert_eq!(Dependency.forward_arrow(LineStyle::Solid), "-->");
+        assert_eq!(Inheritance.forward_arrow(LineStyle::Dotted), "..|>");
+
+        // Longer lines are the same arrow
+        assert_eq!(
+            RelationKind::from_arrow("---->"),
+            Some((Association, false))
+        );
+        assert_eq!(
+            RelationKind::from_arrow("<|...."),
+            Some((Realization, true))
+        );
+        assert_eq!(RelationKind::from_arrow("----"), Some((SolidLink, false)));
+
+        for bogus in ["", "-", ">|--", ">..", "-->>", "<-->", "-.->"] {
+            assert_eq!(RelationKind::from_arrow(bogus), None, "{bogus}");
+        }
+    }
+
+    #[test]
+    fn test_relation_arrow() {
+        let diagram = parse_mermaid(
+            "classDiagram\nA <|-- B\nA ..|> B\nA *-- B\nA o.. B\nA --> B\nA ..> B\nA -- B\nA .. B\nA --() B\nA ----> B\n",
+        )
+        .unwrap();
+        let arrows: Vec<_> = diagram.relations.iter().map(Relation::arrow).collect();
+        assert_eq!(
+            arrows,
+            [
+                "--|>", "..|>", "--*", "..o", "-->", "..>", "--", "..", "--()", "-->"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_notation_is_none() {
+        assert!(TypeNotation::None.is_none());
+        assert!(!TypeNotation::Prefix.is_none());
+        assert!(!TypeNotation::Postfix.is_none());
+    }
+
+    #[test]
+    fn test_annotation_kind_from_str() {
+        assert_eq!(AnnotationKind::from("interface"), AnnotationKind::Interface);
+        assert_eq!(AnnotationKind::from(" Abstract "), AnnotationKind::Abstract);
+        assert_eq!(
+            AnnotationKind::from("ENUMERATION"),
+            AnnotationKind::Enumeration
+        );
+        assert_eq!(AnnotationKind::from("service"), AnnotationKind::Service);
+        assert_eq!(
+            AnnotationKind::from(" entity "),
+            AnnotationKind::Other("entity".into())
+        );
+        assert_eq!(AnnotationKind::from("Entity").as_str(), "Entity");
+        assert_eq!(AnnotationKind::Enumeration.as_str(), "enumeration");
+    }
+
+    #[test]
+    fn test_class_annotation_predicates() {
+        let diagram = parse_mermaid(
+            "classDiagram\nclass Shape {\n  <<Interface>>\n}\nclass Color\nColor : <<enumeration>>\nclass Api\nApi : <<service>>\nclass Plain\n",
+        )
+        .unwrap();
+        let class = |name: &str| &diagram.namespaces[DEFAULT_NAMESPACE].classes[name];
+
+        assert!(class("Shape").is_interface());
+        assert!(!class("Shape").is_enum());
+        assert!(class("Color").is_enum());
+        assert!(class("Api").is_service());
+        assert!(class("Api").has_annotation("SERVICE"));
+        assert_eq!(class("Shape").annotations, [AnnotationKind::Interface]);
+
+        let plain = class("Plain");
+        assert!(!plain.is_interface() && !plain.is_enum() && !plain.is_service());
+    }
+
+    #[test]
+    fn test_diagram_relations_from_and_to() {
+        let diagram = parse_mermaid(
+            "classDiagram\nOrder --> Item\nCustomer --> Order\nOrder ..> Payment\nItem <|-- Order\n",
+        )
+        .unwrap();
+
+        let heads: Vec<_> = diagram
+            .relations_from("Order")
+            .map(|rel| rel.head.as_ref())
+            .collect();
+        assert_eq!(heads, ["Item", "Payment", "Item"]);
+
+        let tails: Vec<_> = diagram
+            .relations_to("Order")
+            .map(|rel| rel.tail.as_ref())
+            .collect();
+        assert_eq!(tails, ["Customer"]);
+
+        assert_eq!(diagram.relations_to("Customer").count(), 0);
+        assert_eq!(diagram.relations_from("Ord").count(), 0);
+    }
+
+    #[test]
+    fn test_diagram_title() {
+        let diagram = parse_mermaid("---\ntitle: Animals\n---\nclassDiagram\n").unwrap();
+        assert_eq!(diagram.title(), Some("Animals"));
+
+        // Frontmatter that is valid YAML but not a mapping has no title
+        for frontmatter in ["just a string", "- a\n- b", "42", "title"] {
+            let source = format!("---\n{frontmatter}\n---\nclassDiagram\n");
+            let diagram = parse_mermaid(&source).unwrap();
+            assert!(diagram.yaml.is_some());
+            assert_eq!(diagram.title(), None, "{frontmatter}");
+        }
+
+        let diagram = parse_mermaid("---\ntitle: [1, 2]\n---\nclassDiagram\n").unwrap();
+        assert_eq!(diagram.title(), None);
+        assert_eq!(parse_mermaid("classDiagram\n").unwrap().title(), None);
+    }
+
+    #[test]
+    fn test_diagram_find_relations() {
+        let diagram = parse_mermaid(
+            "classDiagram\nOrder --> Item : contains\nOrder ..> Item : prices\nItem --> Order\nOrder --> Customer\n",
+        )
+        .unwrap();
+
+        let labels: Vec<_> = diagram
+            .find_relations("Order", "Item")
+            .map(|rel| rel.label.as_deref())
+            .collect();
+        assert_eq!(labels, [Some("contains"), Some("prices")]);
+
+        // The pair is ordered
+        assert_eq!(diagram.find_relations("Item", "Order").count(), 1);
+        assert_eq!(diagram.find_relations("Customer", "Order").count(), 0);
+    }
+
+    #[test]
+    fn test_diagram_rename_class() {
+        let mut diagram = parse_mermaid(
+            "classDiagram\nclass Order {\n  +id: int\n}\nOrder --> Item\nCustomer --> Order\nnote for Order \"placed by a customer\"\nstyle Order fill:#f9f\nnamespace Shop {\n  class Cart\n}\nShop::Cart --> Item\n",
+        )
+        .unwrap();
+
+        assert!(diagram.rename_class("Order", "Purchase"));
+        assert!(diagram.find_class("Order").is_none());
+        let purchase = diagram.find_class("Purchase").unwrap();
+        assert_eq!(purchase.name, "Purchase");
+        assert_eq!(purchase.members.len(), 1);
+        assert_eq!(diagram.relations[0].tail, "Purchase");
+        assert_eq!(diagram.relations[1].head, "Purchase");
+        assert_eq!(diagram.notes[0].target_class, Some("Purchase".into()));
+        assert_eq!(diagram.styles[0].target, "Purchase");
+
+        // Undeclared relation ends are renamed too
+        assert!(diagram.rename_class("Item", "Product"));
+        assert_eq!(diagram.relations[0].head, "Product");
+        assert_eq!(diagram.relations[2].head, "Product");
+
+        // Qualified names, moving between namespaces included
+        assert!(diagram.rename_class("Shop::Cart", "Shop::Basket"));
+        assert_eq!(diagram.find_class("Shop::Basket").unwrap().name, "Basket");
+        assert_eq!(diagram.relations[2].tail, "Shop::Basket");
+        assert!(diagram.rename_class("Shop::Basket", "Basket"));
+        assert!(diagram.find_class("Basket").is_some());
+        assert!(diagram.namespaces["Shop"].classes.is_empty());
+
+        // Refused: the new name is taken or its namespace doesn't exist
+        assert!(!diagram.rename_class("Basket", "Purchase"));
+        assert!(!diagram.rename_class("Basket", "Nowhere::Basket"));
+        assert!(!diagram.rename_class("Missing", "Other"));
+        assert!(diagram.find_class("Basket").is_some());
+    }
+
+    #[test]
+    fn test_diagram_into_owned() {
+        fn parse_owned() -> Diagram<'static> {
+            let source = String::from(
+                "classDiagram\nclass Animal\nAnimal : +int age\nAnimal --> Food : eats\n",
+            );
+            parse_mermaid(&source).unwrap().into_owned()
+        }
+
+        // The source string is gone by the time we look at the diagram
+        let diagram = parse_owned();
+        let animal = diagram.namespaces[DEFAULT_NAMESPACE]
+            .classes
+            .get("Animal")
+            .expect("Animal should exist");
+        assert_eq!(animal.name, "Animal");
+        assert!(matches!(animal.name, Cow::Owned(_)));
+        assert_eq!(diagram.relations[0].label, Some("eats".into()));
+
+        let source = String::from("classDiagram\nclass Animal\n");
+        let borrowed = parse_mermaid(&source).unwrap();
+        let detached = borrowed.to_owned();
+        drop(borrowed);
+        drop(source);
+        assert!(
+            detached.namespaces[DEFAULT_NAMESPACE]
+                .classes
+                .contains_key("Animal")
+        );
+    }
 }
@@ -0,0 +1,140 @@
+//! Semantic checks on a parsed [`Diagram`] that the grammar can't catch, such as a class that
+//! ends up inheriting from itself.
+
+use std::{collections::HashMap, fmt};
+
+use crate::types::{Diagram, RelationKind};
+
+/// Something in a diagram that parses fine but is most likely an authoring mistake
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationWarning {
+    /// The classes inherit from (or realize) each other in a loop, listed in the order they first
+    /// appear in the relations. A class inheriting from itself is a cycle of one.
+    InheritanceCycle { classes: Vec<String> },
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationWarning::InheritanceCycle { classes } => {
+                write!(f, "inheritance cycle between {}", classes.join(", "))
+            }
+        }
+    }
+}
+
+/// Run every check on `diagram`, returning the warnings in a stable order
+pub fn validate(diagram: &Diagram) -> Vec<ValidationWarning> {
+    inheritance_cycles(diagram)
+}
+
+/// Find the groups of classes that inherit from each other in a loop, following
+/// [`RelationKind::Inheritance`] and [`RelationKind::Realization`] relations from subclass to
+/// superclass
+fn inheritance_cycles(diagram: &Diagram) -> Vec<ValidationWarning> {
+    // Classes are numbered in the order they first appear so the output doesn't depend on
+    // hashing
+    let mut ids = HashMap::new();
+    let mut names = Vec::new();
+    let mut edges: Vec<Vec<usize>> = Vec::new();
+    let mut id = |name: &str| {
+        *ids.entry(name.to_owned()).or_insert_with(|| {
+            names.push(name.to_owned());
+            edges.push(Vec::new());
+            names.len() - 1
+        })
+    };
+    let mut pairs = Vec::new();
+    for relation in &diagram.relations {
+        if matches!(
+            relation.kind,
+            RelationKind::Inheritance | RelationKind::Realization
+        ) {
+            pairs.push((id(&relation.tail), id(&relation.head)));
+        }
+    }
+    for (sub, sup) in pairs {
+        edges[sub].push(sup);
+    }
+
+    // Diagrams are small, so working out what each class reaches is cheap enough. Two classes
+    // are in the same cycle when each reaches the other.
+    let reach: Vec<Vec<bool>> = (0..names.len())
+        .map(|start| {
+            let mut seen = vec![false; names.len()];
+            let mut stack = edges[start].clone();
+            while let Some(next) = stack.pop() {
+                if !seen[next] {
+                    seen[next] = true;
+                    stack.extend(&edges[next]);
+                }
+            }
+            seen
+        })
+        .collect();
+
+    let mut reported = vec![false; names.len()];
+    let mut warnings = Vec::new();
+    for start in 0..names.len() {
+        if reported[start] || !reach[start][start] {
+            continue;
+        }
+        let members: Vec<_> = (start..names.len())
+            .filter(|&other| reach[start][other] && reach[other][start])
+            .collect();
+        for &member in &members {
+            reported[member] = true;
+        }
+        warnings.push(ValidationWarning::InheritanceCycle {
+            classes: members
+                .into_iter()
+                .map(|member| names[member].clone())
+                .collect(),
+        });
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parserv2::parse_mermaid;
+
+    #[test]
+    fn test_inheritance_cycle() {
+        let diagram =
+            parse_mermaid("classDiagram\nA --|> B\nB --|> C\nC --|> A\nC --> D\nD --|> E\n")
+                .unwrap();
+        let warnings = validate(&diagram);
+        assert_eq!(
+            warnings,
+            [ValidationWarning::InheritanceCycle {
+                classes: vec!["A".into(), "B".into(), "C".into()],
+            }]
+        );
+        assert_eq!(warnings[0].to_string(), "inheritance cycle between A, B, C");
+    }
+
+    #[test]
+    fn test_inheritance_cycle_kinds() {
+        // Backward arrows, realization and self-inheritance all count, association doesn't
+        let diagram = parse_mermaid(
+            "classDiagram\nShape <|-- Circle\nShape ..|> Circle\nLoop --|> Loop\nA --> B\nB --> A\n",
+        )
+        .unwrap();
+        assert_eq!(
+            validate(&diagram),
+            [
+                ValidationWarning::InheritanceCycle {
+                    classes: vec!["Circle".into(), "Shape".into()],
+                },
+                ValidationWarning::InheritanceCycle {
+                    classes: vec!["Loop".into()],
+                },
+            ]
+        );
+
+        let diagram = parse_mermaid("classDiagram\nB --|> A\nC --|> A\nC --|> B\n").unwrap();
+        assert!(validate(&diagram).is_empty());
+    }
+}
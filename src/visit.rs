@@ -0,0 +1,155 @@
+//! Walk the parts of a [`Diagram`] without writing the nested loops over namespaces by hand
+//!
+//! Implement [`Visitor`] (or [`VisitorMut`] to change things in place), overriding only the
+//! methods for the parts you care about, and hand it to [`walk_diagram`] ([`walk_diagram_mut`]).
+//! Classes are visited in declaration order across all namespaces, each followed by its members,
+//! then relations and notes in the order they appear in the diagram.
+
+use crate::types::{Class, Diagram, Member, Namespace, Note, Relation};
+
+/// Callbacks for [`walk_diagram`]. Every method does nothing by default.
+pub trait Visitor<'source> {
+    fn visit_class(&mut self, _class: &Class<'source>) {}
+    fn visit_member(&mut self, _member: &Member<'source>) {}
+    fn visit_relation(&mut self, _relation: &Relation<'source>) {}
+    fn visit_note(&mut self, _note: &Note<'source>) {}
+}
+
+/// Callbacks for [`walk_diagram_mut`]. Every method does nothing by default.
+pub trait VisitorMut<'source> {
+    fn visit_class_mut(&mut self, _class: &mut Class<'source>) {}
+    fn visit_member_mut(&mut self, _member: &mut Member<'source>) {}
+    fn visit_relation_mut(&mut self, _relation: &mut Relation<'source>) {}
+    fn visit_note_mut(&mut self, _note: &mut Note<'source>) {}
+}
+
+/// Call `visitor` for every class, member, relation and note in `diagram`
+pub fn walk_diagram<'source, V: Visitor<'source> + ?Sized>(
+    visitor: &mut V,
+    diagram: &Diagram<'source>,
+) {
+    let mut classes = Vec::new();
+    let mut stack: Vec<_> = diagram.namespaces.values().collect();
+    while let Some(ns) = stack.pop() {
+        classes.extend(ns.classes.values());
+        stack.extend(ns.children.values());
+    }
+    classes.sort_by(|a, b| (a.order, &a.name).cmp(&(b.order, &b.name)));
+
+    for class in classes {
+        visitor.visit_class(class);
+        for member in &class.members {
+            visitor.visit_member(member);
+        }
+    }
+    for relation in &diagram.relations {
+        visitor.visit_relation(relation);
+    }
+    for note in &diagram.notes {
+        visitor.visit_note(note);
+    }
+}
+
+/// [`walk_diagram`] for a [`VisitorMut`], which may change what it visits. Note that renaming a
+/// class doesn't change the key it is stored under in its namespace.
+pub fn walk_diagram_mut<'source, V: VisitorMut<'source> + ?Sized>(
+    visitor: &mut V,
+    diagram: &mut Diagram<'source>,
+) {
+    let mut classes = Vec::new();
+    let mut stack: Vec<&mut Namespace<'source>> = diagram.namespaces.values_mut().collect();
+    while let Some(ns) = stack.pop() {
+        classes.extend(ns.classes.values_mut());
+        stack.extend(ns.children.values_mut());
+    }
+    classes.sort_by(|a, b| (a.order, &a.name).cmp(&(b.order, &b.name)));
+
+    for class in classes {
+        visitor.visit_class_mut(class);
+        for member in &mut class.members {
+            visitor.visit_member_mut(member);
+        }
+    }
+    for relation in &mut diagram.relations {
+        visitor.visit_relation_mut(relation);
+    }
+    for note in &mut diagram.notes {
+        visitor.visit_note_mut(note);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parserv2::parse_mermaid;
+    use crate::types::Visibility;
+
+    const SOURCE: &str = "classDiagram\nclass Animal {\n  +name: String\n  +eat() void\n  -digest() void\n}\nnamespace Pets {\n  class Dog {\n    +bark() void\n  }\n}\nAnimal <|-- Dog\nnote for Dog \"good boy\"\n";
+
+    #[test]
+    fn test_walk_diagram() {
+        #[derive(Default)]
+        struct Counter {
+            classes: Vec<String>,
+            public_methods: usize,
+            relations: usize,
+            notes: usize,
+        }
+
+        impl<'source> Visitor<'source> for Counter {
+            fn visit_class(&mut self, class: &Class<'source>) {
+                self.classes.push(class.name.to_string());
+            }
+
+            fn visit_member(&mut self, member: &Member<'source>) {
+                if let Member::Method(method) = member
+                    && method.visibility == Visibility::Public
+                {
+                    self.public_methods += 1;
+                }
+            }
+
+            fn visit_relation(&mut self, _relation: &Relation<'source>) {
+                self.relations += 1;
+            }
+
+            fn visit_note(&mut self, _note: &Note<'source>) {
+                self.notes += 1;
+            }
+        }
+
+        let diagram = parse_mermaid(SOURCE).unwrap();
+        let mut counter = Counter::default();
+        walk_diagram(&mut counter, &diagram);
+        assert_eq!(counter.classes, ["Animal", "Dog"]);
+        assert_eq!(counter.public_methods, 2);
+        assert_eq!(counter.relations, 1);
+        assert_eq!(counter.notes, 1);
+    }
+
+    #[test]
+    fn test_walk_diagram_mut() {
+        struct StripVisibility;
+
+        impl<'source> VisitorMut<'source> for StripVisibility {
+            fn visit_member_mut(&mut self, member: &mut Member<'source>) {
+                match member {
+                    Member::Attribute(attr) => attr.visibility = Visibility::Unspecified,
+                    Member::Method(method) => method.visibility = Visibility::Unspecified,
+                }
+            }
+        }
+
+        let mut diagram = parse_mermaid(SOURCE).unwrap();
+        walk_diagram_mut(&mut StripVisibility, &mut diagram);
+        let dog = diagram.find_class("Pets::Dog").unwrap();
+        assert_eq!(dog.members[0].visibility(), Visibility::Unspecified);
+        let animal = diagram.find_class("Animal").unwrap();
+        assert!(
+            animal
+                .members
+                .iter()
+                .all(|member| member.visibility() == Visibility::Unspecified)
+        );
+    }
+}
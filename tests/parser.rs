@@ -8,5 +8,6 @@ mod tests {
         let diagram = mermaid_parser::parserv2::parse_mermaid(mermaid).unwrap();
         let ns = diagram.namespaces.get(DEFAULT_NAMESPACE).unwrap();
         println!("{:?}", diagram);
+        assert!(ns.classes.contains_key("BankAccount"));
     }
 }
@@ -0,0 +1,329 @@
+//! Property-style round-trip test: build random diagrams straight from the AST, serialize them,
+//! parse the output again and check we got the same diagram back.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use mermaid_parser::parserv2::parse_mermaid as parse;
+use mermaid_parser::serializer::serialize_diagram;
+use mermaid_parser::types::{
+    AnnotationKind, Attribute, Class, DEFAULT_NAMESPACE, Diagram, DiagramHeader, Direction,
+    LineStyle, Member, Method, Namespace, Note, NotePlacement, Parameter, Relation, RelationKind,
+    StyleDirective, TypeNotation, Visibility,
+};
+
+const CASES: u64 = 256;
+
+const CLASS_NAMES: &[&str] = &["Animal", "Vehicle", "Order", "Customer", "Shape", "Node"];
+/// Names the serializer has to put in backticks
+const ESCAPED_NAMES: &[&str] = &["Bank Account", "A,B", "Cart:Item", "Map{K}", "Re~x", "Hi!"];
+const MEMBER_NAMES: &[&str] = &["age", "name", "id", "count", "total", "value_2"];
+const TYPE_NAMES: &[&str] = &[
+    "int",
+    "String",
+    "bool",
+    "Order",
+    "float",
+    "List~int~",
+    "Map~K, List~V~~",
+];
+const WORDS: &[&str] = &["owns", "uses", "has many", "is a", "talks to"];
+/// Text with the characters that need quoting or escaping in labels and notes
+const TEXTS: &[&str] = &[
+    "owns",
+    "has many",
+    "say \"hi\"",
+    "C:\\dir\\",
+    "a \\\" b",
+    "key: value",
+    "first; second",
+];
+const VISIBILITIES: &[Visibility] = &[
+    Visibility::Public,
+    Visibility::Private,
+    Visibility::Protected,
+    Visibility::Package,
+    Visibility::Unspecified,
+];
+const DIRECTIONS: &[Direction] = &[
+    Direction::TopBottom,
+    Direction::BottomTop,
+    Direction::LeftRight,
+    Direction::RightLeft,
+];
+/// Every forward arrow; the kind and line style of a relation are read off one of these
+const ARROWS: &[&str] = &[
+    "--|>", "..|>", "--*", "..*", "--o", "..o", "-->", "..>", "--", "..", "--()", "..()",
+];
+const CARDINALITIES: &[&str] = &["1", "*", "0..1", "1..*", "many"];
+const DEFAULT_VALUES: &[&str] = &["0", "-1", "3.5", "true", "\"a\"", "\"two words\""];
+
+/// Small xorshift generator so failures are reproducible from the seed alone
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+fn owned(s: impl Into<String>) -> Cow<'static, str> {
+    Cow::Owned(s.into())
+}
+
+fn gen_type(rng: &mut Rng) -> (Option<Cow<'static, str>>, TypeNotation) {
+    match rng.below(3) {
+        0 => (Some(owned(rng.pick(TYPE_NAMES))), TypeNotation::Prefix),
+        1 => (Some(owned(rng.pick(TYPE_NAMES))), TypeNotation::Postfix),
+        _ => (None, TypeNotation::None),
+    }
+}
+
+fn gen_member(rng: &mut Rng) -> Member<'static> {
+    let visibility = rng.pick(VISIBILITIES);
+    let name = owned(rng.pick(MEMBER_NAMES));
+
+    if rng.chance(50) {
+        let (data_type, type_notation) = gen_type(rng);
+        Member::Attribute(Attribute {
+            visibility,
+            name,
+            data_type,
+            is_static: rng.chance(20),
+            type_notation,
+            span: None,
+        })
+    } else {
+        let parameters = (0..rng.below(3))
+            .map(|_| {
+                let (data_type, type_notation) = gen_type(rng);
+                Parameter {
+                    name: owned(rng.pick(MEMBER_NAMES)),
+                    data_type,
+                    type_notation,
+                    default_value: rng.chance(20).then(|| owned(rng.pick(DEFAULT_VALUES))),
+                }
+            })
+            .collect();
+
+        // The serializer always writes return types after the parameter list
+        let (return_type, return_type_notation) = if rng.chance(60) {
+            (Some(owned(rng.pick(TYPE_NAMES))), TypeNotation::Postfix)
+        } else {
+            (None, TypeNotation::None)
+        };
+
+        Member::Method(Method {
+            visibility,
+            name,
+            parameters,
+            return_type,
+            is_static: rng.chance(20),
+            is_abstract: rng.chance(20),
+            return_type_notation,
+            span: None,
+        })
+    }
+}
+
+fn gen_class(rng: &mut Rng, name: String, order: usize) -> Class<'static> {
+    Class {
+        name: owned(name),
+        generic: rng
+            .chance(20)
+            .then(|| owned(rng.pick(&["T", "K, V", "List~int~"]))),
+        label: rng.chance(20).then(|| owned(rng.pick(WORDS))),
+        css_class: rng
+            .chance(20)
+            .then(|| owned(rng.pick(&["important", "dashed-border"]))),
+        annotations: ["interface", "abstract", "service", "entity"]
+            .into_iter()
+            .filter(|_| rng.chance(10))
+            .map(AnnotationKind::from)
+            .collect(),
+        members: (0..rng.below(4)).map(|_| gen_member(rng)).collect(),
+        order,
+        span: None,
+    }
+}
+
+/// `next_order` numbers the classes the way the parser does: in the order they are written out
+fn gen_namespace(
+    rng: &mut Rng,
+    name: &str,
+    class_names: &[String],
+    next_order: &mut usize,
+) -> Namespace<'static> {
+    Namespace {
+        name: owned(name),
+        classes: class_names
+            .iter()
+            .map(|class| {
+                *next_order += 1;
+                let class = gen_class(rng, class.clone(), *next_order - 1);
+                (class.name.clone(), class)
+            })
+            .collect(),
+        children: HashMap::new(),
+        direction: (name != DEFAULT_NAMESPACE && rng.chance(30)).then(|| rng.pick(DIRECTIONS)),
+    }
+}
+
+/// A class name, now and then one that needs backticks, or ends in a dash which does too
+fn gen_class_name(rng: &mut Rng, suffix: &str) -> String {
+    let name = if rng.chance(20) {
+        rng.pick(ESCAPED_NAMES)
+    } else {
+        rng.pick(CLASS_NAMES)
+    };
+    let dash = if rng.chance(5) { "-" } else { "" };
+    format!("{name}{suffix}{dash}")
+}
+
+/// `classes` holds the names relations can point at, `Ns::Class` for the ones in namespaces
+fn gen_relation(rng: &mut Rng, classes: &[String]) -> Relation<'static> {
+    let cardinality = |rng: &mut Rng| rng.chance(30).then(|| owned(rng.pick(CARDINALITIES)));
+    let arrow = rng.pick(ARROWS);
+    let (kind, _) = RelationKind::from_arrow(arrow).expect("known arrow");
+    Relation {
+        tail: owned(classes[rng.below(classes.len())].clone()),
+        head: owned(classes[rng.below(classes.len())].clone()),
+        kind,
+        line: LineStyle::of_arrow(arrow),
+        cardinality_tail: cardinality(rng),
+        cardinality_head: cardinality(rng),
+        label: rng.chance(40).then(|| owned(rng.pick(TEXTS))),
+        raw_operator: None,
+        // A link without an arrowhead reads the same both ways, so it can't point backward
+        reversed: !arrow.ends_with(['-', '.']) && rng.chance(30),
+        span: None,
+    }
+}
+
+fn gen_diagram(rng: &mut Rng) -> Diagram<'static> {
+    // Unique class names, shared out between the default namespace and named ones
+    let class_count = 1 + rng.below(5);
+    let default_classes: Vec<String> = (0..class_count)
+        .map(|i| gen_class_name(rng, &i.to_string()))
+        .collect();
+
+    let mut next_order = 0;
+    let mut endpoints = default_classes.clone();
+    let mut namespaces = HashMap::new();
+    namespaces.insert(
+        owned(DEFAULT_NAMESPACE),
+        gen_namespace(rng, DEFAULT_NAMESPACE, &default_classes, &mut next_order),
+    );
+    for ns in 0..rng.below(3) {
+        let name = format!("Ns{}", ns);
+        let classes: Vec<String> = (0..rng.below(3))
+            .map(|i| gen_class_name(rng, &format!("{ns}_{i}")))
+            .collect();
+        endpoints.extend(classes.iter().map(|class| format!("{name}::{class}")));
+        let namespace = gen_namespace(rng, &name, &classes, &mut next_order);
+        namespaces.insert(owned(name), namespace);
+    }
+
+    let relations = (0..rng.below(5))
+        .map(|_| gen_relation(rng, &endpoints))
+        .collect();
+
+    let notes = (0..rng.below(3))
+        .map(|_| {
+            let target_class = rng
+                .chance(50)
+                .then(|| owned(default_classes[rng.below(default_classes.len())].clone()));
+            let placement = target_class.as_ref().and_then(|_| {
+                rng.pick(&[
+                    None,
+                    Some(NotePlacement::LeftOf),
+                    Some(NotePlacement::RightOf),
+                    Some(NotePlacement::Over),
+                ])
+            });
+            Note {
+                text: owned(rng.pick(TEXTS)),
+                target_class,
+                placement,
+                span: None,
+            }
+        })
+        .collect();
+
+    let styles = (0..rng.below(2))
+        .map(|_| StyleDirective {
+            target: owned(default_classes[rng.below(default_classes.len())].clone()),
+            declarations: vec![
+                (owned("fill"), owned(format!("#{}", rng.below(1000)))),
+                (owned("stroke-width"), owned(format!("{}px", rng.below(5)))),
+            ],
+        })
+        .collect();
+
+    let directives = (0..rng.below(2))
+        .map(|_| {
+            serde_yml::from_str(&format!("{{init: {{theme: {}}}}}", rng.pick(WORDS)))
+                .expect("valid yaml")
+        })
+        .collect();
+
+    // Written the way the serializer writes it, so the raw text survives the round trip
+    let raw_yaml = rng
+        .chance(25)
+        .then(|| format!("\ntitle: {}\n", rng.pick(CLASS_NAMES)));
+    let yaml = raw_yaml
+        .as_deref()
+        .map(|raw| serde_yml::from_str(raw).expect("valid yaml"));
+
+    Diagram {
+        header: if rng.chance(20) {
+            DiagramHeader::ClassDiagramV2
+        } else {
+            DiagramHeader::ClassDiagram
+        },
+        namespaces,
+        relations,
+        notes,
+        styles,
+        direction: rng.chance(50).then(|| rng.pick(DIRECTIONS)),
+        yaml,
+        raw_yaml: raw_yaml.map(Into::into),
+        directives,
+    }
+}
+
+#[test]
+fn roundtrip_random_diagrams() {
+    for seed in 0..CASES {
+        let mut rng = Rng::new(seed);
+        let diagram = gen_diagram(&mut rng);
+        let output = serialize_diagram(&diagram);
+
+        let parsed = parse(&output)
+            .unwrap_or_else(|e| panic!("seed {seed}: failed to parse:\n{output}\nerror: {e:?}"));
+
+        assert_eq!(
+            diagram, parsed,
+            "seed {seed}: round-trip mismatch for:\n{output}"
+        );
+    }
+}
@@ -1,5 +1,15 @@
+use std::io::Cursor;
+
 use mermaid_parser::parserv2::parse_mermaid as parse;
-use mermaid_parser::serializer::serialize_diagram;
+use mermaid_parser::parserv2::reader::parse_reader;
+use mermaid_parser::parserv2::{ParseOptions, parse_with_options};
+use mermaid_parser::serializer::{
+    SerializeOptions, format, serialize_diagram, serialize_diagram_with, serialize_to,
+};
+use mermaid_parser::types::{
+    AnnotationKind, Diagram, DiagramHeader, Direction, LineStyle, Member, Note, NotePlacement,
+    Relation, RelationKind,
+};
 
 #[test]
 fn test_roundtrip_simple_class() {
@@ -38,6 +48,28 @@ fn test_roundtrip_backtick_names() {
     let diagram2 = parse(&output).unwrap();
 
     assert_eq!(diagram.relations.len(), diagram2.relations.len());
+    assert_eq!(diagram, diagram2);
+}
+
+#[test]
+fn test_roundtrip_backtick_punctuation() {
+    // Each of these would end the name or start something else if written bare
+    for name in ["A,B", "A:B", "A{B", "A~B", "a-"] {
+        let input = format!(
+            "classDiagram\nclass `{name}`\n`{name}` : +id int\n`{name}` --> B\nstyle `{name}` fill:#f9f\n"
+        );
+        let diagram = parse(&input).unwrap();
+        assert!(diagram.find_class(name).is_some(), "{name}");
+
+        let output = serialize_diagram(&diagram);
+        assert!(output.contains(&format!("class `{name}`")), "{output}");
+        assert_eq!(parse(&output).unwrap(), diagram, "{output}");
+    }
+
+    // Qualified names keep their `::` bare
+    let input = "classDiagram\nnamespace Ns {\nclass A\n}\nNs::A --> B\n";
+    let output = serialize_diagram(&parse(input).unwrap());
+    assert!(output.contains("Ns::A --> B"), "{output}");
 }
 
 #[test]
@@ -93,6 +125,54 @@ fn test_roundtrip_members_postfix_notation() {
     assert_eq!(class1.members.len(), class2.members.len());
 }
 
+#[test]
+fn test_roundtrip_generic_parameter() {
+    let input = "classDiagram\nclass Test\nTest : +foo(items: List~Order~, count: int) void\n";
+    let diagram = parse(input).unwrap();
+    let output = serialize_diagram(&diagram);
+
+    println!("Output:\n{}", output);
+    assert!(output.contains("+foo(items: List~Order~, count: int) void"));
+
+    let diagram2 = parse(&output).unwrap();
+    assert_eq!(
+        diagram.namespaces[""].classes["Test"].members,
+        diagram2.namespaces[""].classes["Test"].members
+    );
+}
+
+#[test]
+fn test_roundtrip_param_defaults() {
+    let input = "classDiagram\nclass Test\nTest : +method(x: int = 5, name: String = \"a\") void\n";
+    let diagram = parse(input).unwrap();
+    let output = serialize_diagram(&diagram);
+
+    println!("Output:\n{}", output);
+    assert!(output.contains("+method(x: int = 5, name: String = \"a\") void"));
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_angle_bracket_generic() {
+    // Spacing inside angle brackets is normalized, so the output is `Map<String, Int>`
+    let input = "classDiagram\nclass Test {\n  +items : Map< String , Int >\n}\n";
+    let diagram = parse(input).unwrap();
+    let output = serialize_diagram(&diagram);
+
+    println!("Output:\n{}", output);
+    assert!(output.contains("+items: Map<String, Int>"));
+
+    let diagram2 = parse(&output).unwrap();
+    assert_eq!(diagram, diagram2);
+}
+
+#[test]
+fn test_roundtrip_declaration_order() {
+    let input = "classDiagram\nclass Zebra\nclass Apple\nclass Mango\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(serialize_diagram(&diagram), input);
+}
+
 #[test]
 fn test_roundtrip_relations_with_cardinality() {
     let input = "classDiagram\nclass A\nclass B\nA \"1\" --> \"*\" B : uses\n";
@@ -114,6 +194,446 @@ fn test_roundtrip_relations_with_cardinality() {
     );
 }
 
+#[test]
+fn test_serialize_built_dotted_relations() {
+    let relation = |kind, line| Relation {
+        tail: "A".into(),
+        head: "B".into(),
+        kind,
+        line,
+        cardinality_tail: None,
+        cardinality_head: None,
+        label: None,
+        raw_operator: None,
+        reversed: false,
+        span: None,
+    };
+    let diagram = Diagram {
+        relations: vec![
+            relation(RelationKind::Dependency, LineStyle::Dotted),
+            relation(RelationKind::Realization, LineStyle::Dotted),
+            relation(RelationKind::DashLink, LineStyle::Dotted),
+        ],
+        ..Default::default()
+    };
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, "classDiagram\nA ..> B\nA ..|> B\nA .. B\n");
+    assert_eq!(parse(&output).unwrap().relations, diagram.relations);
+}
+
+#[test]
+fn test_roundtrip_quoted_label() {
+    let input = "classDiagram\nA --> B : \"complex: label\"\nB --> C : \"uses;\"\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.relations[0].label, Some("complex: label".into()));
+    assert_eq!(diagram.relations[1].label, Some("uses;".into()));
+
+    // Quotes are only written back where the label needs them
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(
+        output,
+        "classDiagram\nA --> B : complex: label\nB --> C : \"uses;\"\n"
+    );
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_line_style() {
+    let input = "classDiagram\nA ..> B\nA --> B\nC ..* D\nC --* D\n";
+    let diagram = parse(input).unwrap();
+    let lines: Vec<_> = diagram.relations.iter().map(|rel| rel.line).collect();
+    assert_eq!(
+        lines,
+        [
+            LineStyle::Dotted,
+            LineStyle::Solid,
+            LineStyle::Dotted,
+            LineStyle::Solid
+        ]
+    );
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_raw_operator() {
+    let input = "classDiagram\nA ----> B\nC \"1\" <|.... \"*\" D : is a\nE --> F\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.relations[0].raw_operator, Some("---->".into()));
+    assert_eq!(diagram.relations[1].tail, "D");
+    assert_eq!(diagram.relations[1].raw_operator, Some("<|....".into()));
+    assert_eq!(diagram.relations[2].raw_operator, None);
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_roundtrip_arrow_direction() {
+    let input = "classDiagram\nB <|-- A\nC \"1\" *-- \"*\" D : has\nE --> F\n";
+    let diagram = parse(input).unwrap();
+    let relation = &diagram.relations[0];
+    assert_eq!((relation.tail.as_ref(), relation.head.as_ref()), ("A", "B"));
+    assert!(relation.reversed);
+    assert!(diagram.relations[1].reversed);
+    assert!(!diagram.relations[2].reversed);
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+    assert_eq!(format(input).unwrap(), input);
+
+    // Relations can be turned around to point forward instead
+    let options = SerializeOptions {
+        keep_arrow_direction: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        serialize_diagram_with(&diagram, &options),
+        "classDiagram\nA --|> B\nD \"*\" --* \"1\" C : has\nE --> F\n"
+    );
+}
+
+#[test]
+fn test_roundtrip_lollipop() {
+    let input = "classDiagram\nclass Widget\nWidget --() Clickable\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.relations.len(), 1);
+    assert_eq!(diagram.relations[0].kind, RelationKind::Lollipop);
+    assert_eq!(diagram.relations[0].tail, "Widget");
+    assert_eq!(diagram.relations[0].head, "Clickable");
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+
+    // The interface-first spelling is kept too
+    let input = "classDiagram\nClickable ()-- Widget\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.relations[0].tail, "Widget");
+    let output = serialize_diagram(&diagram);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_style() {
+    let input = "classDiagram\nclass Animal\nstyle Animal fill:#f9f,stroke:#333\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.styles.len(), 1);
+    assert_eq!(diagram.styles[0].target, "Animal");
+    assert_eq!(
+        diagram.styles[0].declarations,
+        [
+            ("fill".into(), "#f9f".into()),
+            ("stroke".into(), "#333".into())
+        ]
+    );
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_class_label() {
+    let input = "classDiagram\nclass a[\"A Label\"]\nclass b[\"B\"] {\n  +id: int\n}\n";
+    let diagram = parse(input).unwrap();
+    let classes = &diagram.namespaces[""].classes;
+    assert_eq!(classes["a"].label, Some("A Label".into()));
+    assert_eq!(classes["b"].label, Some("B".into()));
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_annotation() {
+    let input = "classDiagram\nclass Shape {\n  <<interface>>\n  +area() double\n}\nclass Base {\n  <<abstract>>\n  <<entity>>\n}\nclass Plain\n";
+    let diagram = parse(input).unwrap();
+    let classes = &diagram.namespaces[""].classes;
+    assert_eq!(classes["Shape"].annotations, [AnnotationKind::Interface]);
+    assert_eq!(
+        classes["Base"].annotations,
+        [
+            AnnotationKind::Abstract,
+            AnnotationKind::Other("entity".into())
+        ]
+    );
+    assert!(classes["Plain"].annotations.is_empty());
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_serialize_pretty() {
+    let input = "classDiagram\nclass A {\n  +id: int\n}\nclass B\nnamespace N {\nclass C\nclass D\n}\nA --> B\n";
+    let diagram = parse(input).unwrap();
+    let options = SerializeOptions {
+        blank_line_between_classes: true,
+        indent: 4,
+        ..Default::default()
+    };
+    let output = serialize_diagram_with(&diagram, &options);
+    println!("Output:\n{}", output);
+    assert_eq!(
+        output,
+        "classDiagram\nclass A {\n    +id: int\n}\n\nclass B\nnamespace N {\nclass C\n\nclass D\n}\nA --> B\n"
+    );
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_class_generic() {
+    let input = "classDiagram\nclass Square~Shape~ {\n  +side: int\n}\nclass Map~K, V~[\"Lookup\"]\nSquare --> Map\n";
+    let diagram = parse(input).unwrap();
+    let classes = &diagram.namespaces[""].classes;
+    assert_eq!(classes["Square"].generic, Some("Shape".into()));
+    assert_eq!(classes["Map"].generic, Some("K, V".into()));
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_css_class() {
+    let input = "classDiagram\nclass Account:::important {\n  +balance: int\n}\nclass Ledger[\"Books\"]:::audit\n";
+    let diagram = parse(input).unwrap();
+    let classes = &diagram.namespaces[""].classes;
+    assert_eq!(classes["Account"].css_class, Some("important".into()));
+    assert_eq!(classes["Ledger"].css_class, Some("audit".into()));
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_utf8_names() {
+    // `Cafe\u{301}` spells the accent as a combining mark after a plain `e`
+    let input = "classDiagram\nclass 動物 {\n  +âge: int\n  +naïve(größe: int) Ärger\n}\nclass Cafe\u{301}\n動物 --> Cafe\u{301} : 食べる\n";
+    let diagram = parse(input).unwrap();
+    let animal = diagram.find_class("動物").unwrap();
+    assert_eq!(animal.members[0].name(), "âge");
+    let Member::Method(method) = &animal.members[1] else {
+        panic!("naïve() should be a method");
+    };
+    assert_eq!(method.parameters[0].name, "größe");
+    assert_eq!(method.return_type, Some("Ärger".into()));
+    assert!(diagram.find_class("Cafe\u{301}").is_some());
+    assert_eq!(diagram.relations[0].head, "Cafe\u{301}");
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_unicode_symbols_need_backticks() {
+    // Symbols outside ASCII don't join a name any more than ASCII ones do
+    assert!(parse("classDiagram\nclass A→B\n").is_err());
+    assert!(parse("classDiagram\nclass «interface»\n").is_err());
+
+    for name in ["A→B", "«interface»", "Crab🦀"] {
+        let input = format!("classDiagram\nclass `{name}`\n");
+        let diagram = parse(&input).unwrap();
+        let output = serialize_diagram(&diagram);
+        assert_eq!(output, input);
+        assert_eq!(parse(&output).unwrap(), diagram);
+    }
+}
+
+#[test]
+fn test_roundtrip_generic_members() {
+    let input = "classDiagram\nclass Repo~K, V~ {\n  +items() List~int~\n  +put(K key, V value) void\n  +get(key: K) Map~K, List~V~~\n  -Set~K~ keys\n}\n";
+    let diagram = parse(input).unwrap();
+    let Member::Method(items) = &diagram.namespaces[""].classes["Repo"].members[0] else {
+        panic!("items() should be a method");
+    };
+    assert_eq!(items.return_type, Some("List~int~".into()));
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_serialize_group_members() {
+    let input =
+        "classDiagram\nclass A {\n  +run() void\n  +id: int\n  -stop() void\n  -name: String\n}\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(serialize_diagram(&diagram), input);
+
+    let options = SerializeOptions {
+        group_members: true,
+        ..Default::default()
+    };
+    let output = serialize_diagram_with(&diagram, &options);
+    assert_eq!(
+        output,
+        "classDiagram\nclass A {\n  +id: int\n  -name: String\n  +run() void\n  -stop() void\n}\n"
+    );
+}
+
+#[test]
+fn test_roundtrip_realization_and_dash_link() {
+    let input = "classDiagram\nShape ..|> Drawable\nCircle .. Square\nB <|.. A\n";
+    let diagram = parse(input).unwrap();
+    let kinds: Vec<_> = diagram.relations.iter().map(|rel| rel.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            RelationKind::Realization,
+            RelationKind::DashLink,
+            RelationKind::Realization
+        ]
+    );
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_self_loop() {
+    let input = "classDiagram\nclass A\nA --> A\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.relations.len(), 1);
+    assert_eq!(diagram.relations[0].tail, "A");
+    assert_eq!(diagram.relations[0].head, "A");
+
+    let output = serialize_diagram(&diagram);
+
+    println!("Output:\n{}", output);
+
+    let diagram2 = parse(&output).unwrap();
+
+    assert_eq!(diagram2.relations.len(), 1);
+    assert_eq!(diagram2.relations[0].tail, "A");
+    assert_eq!(diagram2.relations[0].head, "A");
+    assert_eq!(diagram.relations[0].kind, diagram2.relations[0].kind);
+}
+
+#[test]
+fn test_roundtrip_header_v2() {
+    let input = "classDiagram-v2\nclass Test\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.header, DiagramHeader::ClassDiagramV2);
+
+    let output = serialize_diagram(&diagram);
+    assert!(output.starts_with("classDiagram-v2\n"));
+
+    let diagram2 = parse(&output).unwrap();
+    assert_eq!(diagram.header, diagram2.header);
+
+    let plain = parse("classDiagram\nclass Test\n").unwrap();
+    assert_eq!(plain.header, DiagramHeader::ClassDiagram);
+    assert!(serialize_diagram(&plain).starts_with("classDiagram\n"));
+}
+
+#[test]
+fn test_roundtrip_frontmatter_only() {
+    let diagram = Diagram {
+        yaml: Some(serde_yml::from_str("title: Metadata only").unwrap()),
+        ..Default::default()
+    };
+    let output = serialize_diagram(&diagram);
+
+    println!("Output:\n{}", output);
+    assert_eq!(output, "---\ntitle: Metadata only\n---\nclassDiagram\n");
+
+    let diagram2 = parse(&output).unwrap();
+
+    assert_eq!(diagram.yaml, diagram2.yaml);
+    assert!(diagram2.relations.is_empty());
+    assert!(diagram2.notes.is_empty());
+    assert!(diagram2.namespaces.values().all(|ns| ns.classes.is_empty()));
+}
+
+#[test]
+fn test_roundtrip_frontmatter_with_dashes() {
+    let input = "---\ntitle: \"a --- b\"\n---\nclassDiagram\nclass A\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.title(), Some("a --- b"));
+    assert!(diagram.find_class("A").is_some());
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    let diagram2 = parse(&output).unwrap();
+    assert_eq!(diagram2.title(), Some("a --- b"));
+    assert_eq!(diagram2.class_count(), 1);
+}
+
+#[test]
+fn test_format_preserves_frontmatter() {
+    let frontmatter = "---\nzoo: 'yes'\n# keep me\ntitle:   Animals\nconfig: {theme: dark}\n---\n";
+    let input = format!("{frontmatter}classDiagram\nclass  Animal\nAnimal-->Food\n");
+
+    let output = format(&input).unwrap();
+    println!("Output:\n{}", output);
+    assert_eq!(
+        output,
+        format!("{frontmatter}classDiagram\nclass Animal\nAnimal --> Food\n")
+    );
+    assert_eq!(format(&output).unwrap(), output);
+
+    // Re-serializing the parsed YAML loses the comment and the spacing
+    let diagram = parse(&input).unwrap();
+    assert_eq!(diagram.title(), Some("Animals"));
+    assert!(!serialize_diagram(&diagram).starts_with(frontmatter));
+
+    // Without any raw text the parsed YAML is written
+    let diagram = Diagram {
+        raw_yaml: None,
+        ..diagram
+    };
+    let options = SerializeOptions {
+        preserve_frontmatter: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        serialize_diagram_with(&diagram, &options),
+        serialize_diagram(&diagram)
+    );
+}
+
+#[test]
+fn test_roundtrip_directives() {
+    let input = "%%{init: {'theme': 'forest', 'themeVariables': {'fontSize': 12}}}%%\nclassDiagram\n%%{wrap}%%\nclass A\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.directives[0]["init"]["theme"], "forest");
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert!(output.starts_with(
+        "%%{init: {\"theme\": \"forest\", \"themeVariables\": {\"fontSize\": 12}}}%%\n%%{wrap}%%\nclassDiagram\n"
+    ));
+
+    let diagram2 = parse(&output).unwrap();
+    assert_eq!(diagram, diagram2);
+}
+
 #[test]
 fn test_roundtrip_direction() {
     let input = "classDiagram\ndirection RL\nclass Test\n";
@@ -136,6 +656,139 @@ fn test_roundtrip_notes() {
     let diagram2 = parse(&output).unwrap();
 
     assert_eq!(diagram.notes.len(), diagram2.notes.len());
+    assert_eq!(diagram, diagram2);
+}
+
+#[test]
+fn test_roundtrip_note_placement() {
+    let input = "classDiagram\nclass A\nnote left of A \"x\"\nnote right of A \"y\"\nnote over A \"z\"\nnote for A \"w\"\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.notes[0].placement, Some(NotePlacement::LeftOf));
+    assert_eq!(diagram.notes[3].placement, None);
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_serialize_wrap_notes() {
+    let input = "classDiagram\nclass A\nnote for A \"keeps track of every order a customer placed\"\nnote \"short\"\n";
+    let diagram = parse(input).unwrap();
+    let options = SerializeOptions {
+        wrap_notes: Some(20),
+        ..Default::default()
+    };
+
+    let output = serialize_diagram_with(&diagram, &options);
+    println!("Output:\n{}", output);
+    assert_eq!(
+        output,
+        "classDiagram\nclass A\nnote for A \"keeps track of every<br/>order a customer<br/>placed\"\nnote \"short\"\n"
+    );
+
+    // The breaks are read back as part of the text, and wrapping again changes nothing
+    let diagram2 = parse(&output).unwrap();
+    assert_eq!(
+        diagram2.notes[0].text,
+        "keeps track of every<br/>order a customer<br/>placed"
+    );
+    assert_eq!(serialize_diagram_with(&diagram2, &options), output);
+}
+
+#[test]
+fn test_roundtrip_multiline_note() {
+    let input = "classDiagram\nclass A\nclass B\nnote for A \"line one\nline two\"\nnote \"x\r\ny\"\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.notes[0].text, "line one\nline two");
+    assert_eq!(diagram.notes[1].text, "x\r\ny");
+    assert_eq!(diagram.class_count(), 2);
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+
+    // Reading line by line gives the same diagram
+    let read = parse_reader(Cursor::new(&output)).unwrap();
+    assert_eq!(read, diagram);
+
+    // The statement after the note is found, and errors past it point at the right line
+    let err = parse("classDiagram\nnote \"a\nb\"\n!!!\n").unwrap_err();
+    assert_eq!(err.to_string(), "expected a statement at line 4: !!!");
+    let err = parse_reader(Cursor::new("classDiagram\nnote \"a\nb\"\n!!!\n")).unwrap_err();
+    assert_eq!(err.to_string(), "expected a statement at line 4: !!!");
+}
+
+#[test]
+fn test_roundtrip_note_escaped_quote() {
+    let input = "classDiagram\nclass Test\nnote for Test \"say \\\"hi\\\" twice\"\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.notes[0].text, "say \"hi\" twice");
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert!(output.contains(r#"note for Test "say \"hi\" twice""#));
+
+    let diagram2 = parse(&output).unwrap();
+    assert_eq!(diagram, diagram2);
+}
+
+#[test]
+fn test_roundtrip_note_backslashes() {
+    let mut diagram = parse("classDiagram\nclass Test\n").unwrap();
+    for text in [r"C:\dir\", r#"a literal \" in the text"#, r"C:\dir"] {
+        diagram.notes = vec![Note {
+            text: text.into(),
+            target_class: Some("Test".into()),
+            placement: None,
+            span: None,
+        }];
+        let output = serialize_diagram(&diagram);
+        println!("Output:\n{}", output);
+        assert_eq!(parse(&output).unwrap(), diagram, "{text}");
+    }
+
+    let input = "classDiagram\nclass Test\nnote for Test \"C:\\dir\\\\\"\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.notes[0].text, r"C:\dir\");
+
+    // Every backslash is escaped on the way out
+    let output = serialize_diagram(&diagram);
+    assert!(output.contains(r#"note for Test "C:\\dir\\""#));
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_entities() {
+    let input = "classDiagram\nA --> B : #quot;uses#quot; #35;1\nnote \"#quot;hi#quot;\"\n";
+    let parse_options = ParseOptions {
+        decode_entities: true,
+        ..Default::default()
+    };
+    let diagram = parse_with_options(input, &parse_options).unwrap();
+    assert_eq!(diagram.notes[0].text, "\"hi\"");
+    assert_eq!(diagram.relations[0].label, Some("\"uses\" #1".into()));
+
+    // Verbatim by default
+    let plain = parse(input).unwrap();
+    assert_eq!(plain.notes[0].text, "#quot;hi#quot;");
+
+    let options = SerializeOptions {
+        encode_entities: true,
+        ..Default::default()
+    };
+    let output = serialize_diagram_with(&diagram, &options);
+    println!("Output:\n{}", output);
+    assert_eq!(
+        output,
+        "classDiagram\nA --> B : #quot;uses#quot; #1\nnote \"#quot;hi#quot;\"\n"
+    );
+    assert_eq!(
+        parse_with_options(&output, &parse_options).unwrap(),
+        diagram
+    );
 }
 
 #[test]
@@ -159,6 +812,59 @@ fn test_roundtrip_namespace() {
     let ns1 = diagram.namespaces.get("MyNamespace").unwrap();
     let ns2 = diagram2.namespaces.get("MyNamespace").unwrap();
     assert_eq!(ns1.classes.len(), ns2.classes.len());
+    assert_eq!(diagram, diagram2);
+}
+
+#[test]
+fn test_roundtrip_namespace_order() {
+    // Namespace blocks stay where they were among the classes around them
+    let input = "classDiagram\nclass A\nnamespace N {\nclass B\n}\nclass C\n";
+    let diagram = parse(input).unwrap();
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_nested_namespace() {
+    let input = "classDiagram\nclass A\nnamespace Outer {\nclass B\nnamespace Inner {\nclass Leaf\n}\nclass C\n}\nOuter::Inner::Leaf --> A\n";
+    let diagram = parse(input).unwrap();
+    assert!(diagram.find_class("Outer::Inner::Leaf").is_some());
+    assert!(diagram.unresolved_endpoints().is_empty());
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_namespace_backtick_name() {
+    let input = "classDiagram\nnamespace `My NS` {\nclass A\n}\n";
+    let diagram = parse(input).unwrap();
+    assert!(diagram.namespaces["My NS"].classes.contains_key("A"));
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
+}
+
+#[test]
+fn test_roundtrip_namespace_direction() {
+    let input = "classDiagram\ndirection TB\nnamespace N {\ndirection LR\nclass A\n}\n";
+    let diagram = parse(input).unwrap();
+    assert_eq!(diagram.direction, Some(Direction::TopBottom));
+    assert_eq!(
+        diagram.namespaces["N"].direction,
+        Some(Direction::LeftRight)
+    );
+
+    let output = serialize_diagram(&diagram);
+    println!("Output:\n{}", output);
+    assert_eq!(output, input);
+    assert_eq!(parse(&output).unwrap(), diagram);
 }
 
 #[test]
@@ -184,6 +890,7 @@ note for Vehicle "Vehicles are fast"
     println!("Serialized:\n{}", output);
 
     let diagram2 = parse(&output).unwrap();
+    assert_eq!(diagram, diagram2);
 
     // Verify all major components
     assert_eq!(diagram.direction, diagram2.direction);
@@ -199,3 +906,68 @@ note for Vehicle "Vehicles are fast"
 
     assert_eq!(total_classes1, total_classes2);
 }
+
+#[test]
+fn test_roundtrip_space_after_classifier() {
+    let input = "classDiagram\nclass Test\nTest : +$ count: int\nTest : +$*create() Test\n";
+    let diagram = parse(input).unwrap();
+
+    let compact = serialize_diagram(&diagram);
+    let spaced = serialize_diagram_with(
+        &diagram,
+        &SerializeOptions {
+            space_after_classifier: true,
+            ..Default::default()
+        },
+    );
+
+    println!("Compact:\n{}", compact);
+    println!("Spaced:\n{}", spaced);
+
+    assert!(compact.contains("+$count: int"));
+    assert!(compact.contains("+$*create() Test"));
+    assert!(spaced.contains("+$ count: int"));
+    assert!(spaced.contains("+$* create() Test"));
+
+    fn members(src: &str) -> Vec<Member<'_>> {
+        let diagram = parse(src).unwrap();
+        diagram.namespaces[""].classes["Test"].members.clone()
+    }
+    assert_eq!(members(&compact), members(&spaced));
+    assert_eq!(members(&compact), members(input));
+}
+
+#[test]
+fn test_serialize_to_matches_serialize_diagram() {
+    let input = r#"classDiagram
+direction LR
+class Animal
+Animal : +int age
+Animal : +move(distance: int) void
+namespace Zoo {
+class Keeper
+}
+Animal "1" --> "*" Animal : parent of
+note for Animal "Animals move"
+"#;
+    let diagram = parse(input).unwrap();
+
+    let mut streamed = String::new();
+    serialize_to(&diagram, &mut streamed).unwrap();
+
+    assert_eq!(streamed, serialize_diagram(&diagram));
+}
+
+#[test]
+fn test_serialize_to_propagates_errors() {
+    struct Full;
+
+    impl std::fmt::Write for Full {
+        fn write_str(&mut self, _: &str) -> std::fmt::Result {
+            Err(std::fmt::Error)
+        }
+    }
+
+    let diagram = parse("classDiagram\nclass Animal\n").unwrap();
+    assert!(serialize_to(&diagram, &mut Full).is_err());
+}